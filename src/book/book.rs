@@ -1,48 +1,409 @@
 use std::collections::VecDeque;
+use std::fmt;
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-use walkdir::{DirEntry, WalkDir};
+use pulldown_cmark::{Event, Parser, Tag};
+use walkdir::WalkDir;
 
-use crate::book::is_hidden;
+use crate::book::{is_hidden, parse_summary_file, FrontMatter, SummaryEntry, SUMMARY_FILE};
+use crate::config::{BookConfig, Config, BOOK_CONFIG_FILE};
 use crate::errors::*;
+use crate::render::{event_text, heading_id};
 
-pub fn load_book<P: AsRef<Path>>(src_dir: P) -> Result<Book> {
+/// The prefix of the files and directories of draft chapters and books,
+/// e.g. `_draft-intro.md`.
+pub const DRAFT_PREFIX: &str = "_draft";
+
+/// Load the book in the directory `src_dir`, leaving out its draft chapters
+/// unless the config's `build.drafts` is set.
+pub fn load_book<P: AsRef<Path>>(src_dir: P, config: &Config) -> Result<Book> {
     trace!("{:?}", src_dir.as_ref());
 
-    let mut chapters = Vec::new();
+    let mut chapters = load_chapters(src_dir.as_ref());
 
-    let walker = WalkDir::new(src_dir.as_ref()).max_depth(1).into_iter();
+    let summary_file = src_dir.as_ref().join(SUMMARY_FILE);
+    if summary_file.exists() {
+        debug!("Ordering chapters by {}", summary_file.display());
+        let parsed = parse_summary_file(&summary_file)?;
+        let ordered = make_chapters_from_entries(src_dir.as_ref(), &parsed.entries)
+            .chain_err(|| format!("Unable to load {}", summary_file.display()))?;
 
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
-        let entry = entry.unwrap();
-        let filename = entry.path().file_stem().unwrap().to_str().unwrap();
-        if entry.path().is_file() && !filename.to_lowercase().eq("readme") {
-            trace!("{}", entry.path().display());
-            chapters.push(Chapter {
-                name: filename.into(),
-                path: entry.into_path(),
-            });
+        let mut listed = Vec::new();
+        chapter_paths(&ordered, &mut listed);
+        let mut walked = Vec::new();
+        chapter_paths(&chapters, &mut walked);
+
+        let unlisted = walked.iter()
+            .filter(|path| !listed.contains(path))
+            .filter(|path| !has_draft_name(path.strip_prefix(src_dir.as_ref()).unwrap_or(path)));
+        for path in unlisted {
+            warn!("{} is not listed in {}, skipping it", path.display(), summary_file.display());
         }
+
+        chapters = ordered;
     }
 
-    let name = src_dir.as_ref().to_path_buf()
-        .file_stem().unwrap().to_str().unwrap().to_string();
+    read_chapters(&mut chapters, summary_file.exists())?;
+
+    let (front_matter, readme, front_matter_lines) = read_readme(src_dir.as_ref())?;
+    let book_config = load_book_config(src_dir.as_ref())?;
+
+    let stem = src_dir.as_ref().file_stem().unwrap().to_str().unwrap();
+    let name = book_config.book.title.clone()
+        .unwrap_or_else(|| page_title(&front_matter, &readme, stem));
+
+    // The README's front matter stands in for the metadata
+    let metadata = &book_config.book;
+    let authors = match metadata.authors {
+        ref authors if !authors.is_empty() => authors.clone(),
+        _ => front_matter.authors.clone(),
+    };
+    let tags = match metadata.tags {
+        ref tags if !tags.is_empty() => tags.clone(),
+        _ => front_matter.tags.clone(),
+    };
+    let description = metadata.description.clone().or_else(|| front_matter.description.clone());
+    let cover = metadata.cover.clone();
+    let language = metadata.language.clone();
 
     let path = src_dir.as_ref().to_path_buf();
+    let draft = is_draft(&path, &front_matter, &book_config);
+
+    mark_drafts(&mut chapters, &path, &book_config, false);
+    if !config.build.drafts {
+        remove_drafts(&mut chapters);
+    }
 
     Ok(Book {
         name,
         root:path,
+        readme,
+        front_matter,
+        front_matter_lines,
         chapters,
+        config: book_config,
+        draft,
+        authors,
+        description,
+        cover,
+        language,
+        tags,
         ..Default::default()
     })
 }
 
+/// Read a Markdown file, splitting off its front matter, along with the
+/// number of lines the front matter took.
+fn read_markdown(path: &Path) -> Result<(FrontMatter, String, usize)> {
+    let text = read_to_string(path)
+        .chain_err(|| format!("Unable to read {}", path.display()))?;
+    let (front_matter, content) = FrontMatter::split(&text)
+        .chain_err(|| format!("Invalid front matter in {}", path.display()))?;
+
+    // The content is what's left at the end of the text
+    let lines = text[..text.len() - content.len()].matches('\n').count();
+    Ok((front_matter, content.to_string(), lines))
+}
+
+/// Read the `README.md` of the book in the directory `root`, if any.
+fn read_readme(root: &Path) -> Result<(FrontMatter, String, usize)> {
+    let readme_file = root.join("README.md");
+    if readme_file.is_file() {
+        read_markdown(&readme_file)
+    } else {
+        Ok((FrontMatter::default(), String::new(), 0))
+    }
+}
+
+/// Load the `book.toml` of the book in the directory `root`, falling back to
+/// the defaults when there is none.
+pub fn load_book_config(root: &Path) -> Result<BookConfig> {
+    let config_file = root.join(BOOK_CONFIG_FILE);
+    if config_file.is_file() {
+        BookConfig::from_disk(&config_file)
+    } else {
+        Ok(BookConfig::default())
+    }
+}
+
+/// Whether the book in the directory `root` is a draft, as marked by its
+/// directory's name, the front matter of its `README.md` or its `book.toml`.
+pub fn is_draft_book(root: &Path) -> Result<bool> {
+    let (front_matter, _, _) = read_readme(root)?;
+    let config = load_book_config(root)?;
+
+    Ok(is_draft(root, &front_matter, &config))
+}
+
+fn is_draft(root: &Path, front_matter: &FrontMatter, config: &BookConfig) -> bool {
+    front_matter.draft || config.draft || has_draft_prefix(root)
+}
+
+/// Whether a file or directory of a book, given relative to the book's
+/// directory, is a draft or inside one, as marked by its name or the book's
+/// `drafts` list.
+pub fn is_draft_path(relative: &Path, config: &BookConfig) -> bool {
+    relative.ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| has_draft_prefix(path) || config.drafts.iter().any(|draft| draft == path))
+}
+
+/// Whether a relative path or one of its parents is named as a draft.
+pub fn has_draft_name(relative: &Path) -> bool {
+    relative.ancestors().any(has_draft_prefix)
+}
+
+fn has_draft_prefix(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(DRAFT_PREFIX))
+}
+
+/// Mark the draft chapters, as marked by their file's name, their front
+/// matter or the book's `drafts` list. The chapters of a draft section are
+/// drafts as well.
+fn mark_drafts(chapters: &mut [BookItem], root: &Path, config: &BookConfig, in_draft: bool) {
+    for item in chapters {
+        if let BookItem::Chapter(ch) = item {
+            let relative = ch.path.strip_prefix(root).expect("Chapters are always inside a book");
+            let file = if ch.is_section() { relative.parent().unwrap_or(relative) } else { relative };
+
+            ch.draft = in_draft
+                || ch.front_matter.draft
+                || has_draft_prefix(file)
+                || config.drafts.iter().any(|draft| draft == file || draft == relative);
+
+            mark_drafts(&mut ch.sub_items, root, config, ch.draft);
+        }
+    }
+}
+
+fn remove_drafts(chapters: &mut Vec<BookItem>) {
+    chapters.retain(|item| match item {
+        BookItem::Chapter(ch) if ch.draft => {
+            warn!("Skipping the draft {}", ch.path.display());
+            false
+        }
+        _ => true,
+    });
+
+    for item in chapters {
+        if let BookItem::Chapter(ch) = item {
+            remove_drafts(&mut ch.sub_items);
+        }
+    }
+}
+
+/// The name of the book in the directory `root`: the title of its
+/// `book.toml`, else taken from its `README.md` like a chapter's title, or
+/// else the directory's name.
+pub fn book_name(root: &Path) -> Result<String> {
+    if let Some(title) = load_book_config(root)?.book.title {
+        return Ok(title);
+    }
+
+    let stem = root.file_stem().unwrap().to_str().unwrap();
+    let (front_matter, readme, _) = read_readme(root)?;
+    Ok(page_title(&front_matter, &readme, stem))
+}
+
+/// The title of a page: the one of its front matter, else the text of its
+/// first level 1 heading, else `fallback`.
+fn page_title(front_matter: &FrontMatter, content: &str, fallback: &str) -> String {
+    front_matter.title.clone()
+        .or_else(|| first_heading(content))
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+/// The text of the first level 1 heading of some Markdown, without its
+/// custom `{#id}`, if any.
+fn first_heading(content: &str) -> Option<String> {
+    let mut inner: Option<Vec<Event>> = None;
+
+    for event in Parser::new(content) {
+        match event {
+            Event::Start(Tag::Header(1)) => inner = Some(Vec::new()),
+            Event::End(Tag::Header(1)) => {
+                let mut inner = inner.take().expect("headings are balanced");
+                heading_id(&mut inner, &mut Default::default());
+
+                let title = event_text(&inner).trim().to_string();
+                if !title.is_empty() {
+                    return Some(title);
+                }
+            }
+            event => {
+                if let Some(ref mut inner) = inner {
+                    inner.push(event);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Find the chapters of a book's directory, in filename order. Every
+/// subdirectory holding Markdown files becomes a section, a chapter whose
+/// page is its `README.md`, if any, and whose sub-items are its chapters.
+fn load_chapters(dir: &Path) -> Vec<BookItem> {
+    let mut chapters = Vec::new();
+
+    let walker = WalkDir::new(dir)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter();
+
+    for entry in walker.filter_entry(|e| !is_hidden(e)) {
+        let entry = entry.unwrap();
+        let path = entry.path();
+
+        if path.is_file() && is_chapter(path) {
+            trace!("{}", path.display());
+            let filename = path.file_stem().unwrap().to_str().unwrap().to_string();
+            chapters.push(BookItem::Chapter(
+                Chapter::new(&filename, String::new(), entry.into_path())));
+        } else if path.is_dir() {
+            let sub_items = load_chapters(path);
+            if sub_items.is_empty() && !path.join("README.md").is_file() {
+                // e.g. a directory of images
+                continue;
+            }
+
+            trace!("{}", path.display());
+            let filename = path.file_name().unwrap().to_str().unwrap().to_string();
+            let mut section = Chapter::new(&filename, String::new(), path.join("README.md"));
+            section.sub_items = sub_items;
+            chapters.push(BookItem::Chapter(section));
+        }
+    }
+
+    chapters
+}
+
+/// The paths of every chapter among some items, recursively.
+fn chapter_paths(items: &[BookItem], paths: &mut Vec<PathBuf>) {
+    for item in items {
+        if let BookItem::Chapter(ch) = item {
+            paths.push(ch.path.clone());
+            chapter_paths(&ch.sub_items, paths);
+        }
+    }
+}
+
+/// Read the content and front matter of every chapter, recursively, a
+/// section's being its `README.md` if it has one. Unless the chapters come
+/// from the book's `SUMMARY.md`, which titles and orders them, they're
+/// titled from their files and sorted by the `order` of their front matter.
+fn read_chapters(chapters: &mut [BookItem], from_summary: bool) -> Result<()> {
+    for item in chapters.iter_mut() {
+        if let BookItem::Chapter(ch) = item {
+            if ch.path.is_file() {
+                let (front_matter, content, lines) = read_markdown(&ch.path)?;
+                if !from_summary {
+                    ch.name = page_title(&front_matter, &content, &ch.name);
+                }
+                ch.content = content;
+                ch.front_matter = front_matter;
+                ch.front_matter_lines = lines;
+            }
+
+            read_chapters(&mut ch.sub_items, from_summary)?;
+        }
+    }
+
+    if !from_summary {
+        // Chapters with an `order` go first, the others keep their filename order
+        chapters.sort_by_key(|item| match item {
+            BookItem::Chapter(ch) => ch.front_matter.order.map_or((1, 0), |order| (0, order)),
+            BookItem::Separator => (1, 0),
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether a file of a book is one of its chapters, i.e. a Markdown file
+/// other than the `README.md` and `SUMMARY.md`.
+fn is_chapter(path: &Path) -> bool {
+    let is_markdown = path.extension().is_some_and(|ext| ext == "md");
+    let filename = path.file_name().unwrap().to_str().unwrap();
+
+    is_markdown
+        && !filename.eq_ignore_ascii_case("README.md")
+        && !filename.eq_ignore_ascii_case(SUMMARY_FILE)
+}
+
+/// Build the chapter tree from the entries of a book's `SUMMARY.md`. An
+/// entry may be a chapter, or a section given by its directory or its
+/// `README.md`.
+fn make_chapters_from_entries(src_dir: &Path, entries: &[SummaryEntry]) -> Result<Vec<BookItem>> {
+    let mut chapters = Vec::new();
+
+    for entry in entries {
+        let link = match entry {
+            SummaryEntry::Link(link) => link,
+            SummaryEntry::Separator => {
+                chapters.push(BookItem::Separator);
+                continue;
+            }
+        };
+
+        let mut path = src_dir.join(&link.location);
+        if path.is_dir() && path != src_dir {
+            path.push("README.md");
+        }
+
+        let is_section = path.file_name().is_some_and(|name| name == "README.md")
+            && path.parent() != Some(src_dir);
+        if !(is_section || path.is_file() && is_chapter(&path)) {
+            bail!(ErrorKind::ParseError(
+                link.line, link.col,
+                format!("{} is not a chapter of the book", link.location.display())));
+        }
+
+        let mut chapter = Chapter::new(&link.title, String::new(), path);
+        chapter.sub_items = make_chapters_from_entries(src_dir, &link.nested_items)?;
+        chapters.push(BookItem::Chapter(chapter));
+    }
+
+    Ok(chapters)
+}
+
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Book {
     pub name: String,
     pub root: PathBuf,
-    pub chapters: Vec<Chapter>,
+    /// The Markdown content of the book's `README.md`.
+    pub readme: String,
+    /// The front matter of the book's `README.md`.
+    pub front_matter: FrontMatter,
+    /// How many lines of the `README.md` the front matter takes, to report
+    /// the lines of `readme` as lines of the file.
+    pub front_matter_lines: usize,
+    /// Where each line of `readme` was written, once other files are
+    /// included in it.
+    #[serde(skip)]
+    pub source_lines: Vec<SourceLine>,
+    pub chapters: Vec<BookItem>,
+    /// The book's own configuration, from its `book.toml`.
+    pub config: BookConfig,
+    /// Whether the book is a work in progress, left out of the library
+    /// unless drafts are built.
+    pub draft: bool,
+    /// The book's authors, from its `book.toml` or else its `README.md`.
+    pub authors: Vec<String>,
+    /// A short description of the book, from its `book.toml` or else its
+    /// `README.md`.
+    pub description: Option<String>,
+    /// An image showing the book, relative to its directory.
+    pub cover: Option<PathBuf>,
+    /// The language the book is written in, when it's not the library's.
+    pub language: Option<String>,
+    /// The book's tags, from its `book.toml` or else its `README.md`.
+    pub tags: Vec<String>,
+    #[serde(skip)]
     __non_exhaustive: (),
 }
 
@@ -57,37 +418,410 @@ impl Book {
             items: self.chapters.iter().collect(),
         }
     }
+
+    /// Recursively apply a closure to each item in the book, allowing you to
+    /// mutate them.
+    pub fn for_each_mut<F>(&mut self, mut func: F)
+        where
+            F: FnMut(&mut BookItem),
+    {
+        for_each_mut(&mut func, &mut self.chapters);
+    }
+
+    /// Complete the translation of the `original` book with the chapters it
+    /// lacks, matched by their path in each book's directory. The chapters
+    /// follow the original's order, the ones only found in the translation
+    /// going last.
+    pub fn add_fallback_chapters(&mut self, original: &Book) {
+        let mut translated = Vec::new();
+        take_chapters(std::mem::take(&mut self.chapters), &self.root, &mut translated);
+
+        self.chapters = merge_chapters(&original.chapters, &original.root, &self.root, &mut translated);
+        self.chapters.extend(translated.into_iter().map(|(_, ch)| BookItem::Chapter(ch)));
+    }
+}
+
+/// Move the chapters out of their tree, each with its path in the book.
+fn take_chapters(items: Vec<BookItem>, root: &Path, chapters: &mut Vec<(PathBuf, Chapter)>) {
+    for item in items {
+        if let BookItem::Chapter(mut ch) = item {
+            let sub_items = std::mem::take(&mut ch.sub_items);
+            let relative = ch.path.strip_prefix(root)
+                .expect("Chapters are always inside a book")
+                .to_path_buf();
+            chapters.push((relative, ch));
+            take_chapters(sub_items, root, chapters);
+        }
+    }
+}
+
+fn merge_chapters(items: &[BookItem],
+                  original_root: &Path,
+                  root: &Path,
+                  translated: &mut Vec<(PathBuf, Chapter)>) -> Vec<BookItem> {
+    let mut chapters = Vec::new();
+
+    for item in items {
+        let original = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::Separator => {
+                chapters.push(BookItem::Separator);
+                continue;
+            }
+        };
+
+        let relative = original.path.strip_prefix(original_root)
+            .expect("Chapters are always inside a book");
+        let mut chapter = match translated.iter().position(|(path, _)| path == relative) {
+            Some(position) => translated.remove(position).1,
+            None => {
+                debug!("{} isn't translated, falling back to {}",
+                       root.join(relative).display(), original.path.display());
+                // Rendered where the translation would be
+                let mut chapter = original.clone();
+                chapter.path = root.join(relative);
+                chapter.fallback = true;
+                chapter
+            }
+        };
+
+        chapter.sub_items = merge_chapters(&original.sub_items, original_root, root, translated);
+        chapters.push(BookItem::Chapter(chapter));
+    }
+
+    chapters
+}
+
+fn for_each_mut<F>(func: &mut F, items: &mut [BookItem])
+    where
+        F: FnMut(&mut BookItem),
+{
+    for item in items {
+        func(item);
+        if let BookItem::Chapter(ch) = item {
+            for_each_mut(func, &mut ch.sub_items);
+        }
+    }
+}
+
+/// An item of a book's table of contents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum BookItem {
+    /// A chapter of the book.
+    Chapter(Chapter),
+    /// A separator between chapters.
+    Separator,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Chapter {
     /// The chapter's name.
     pub name: String,
-    /// The chapter's location, relative to the `README.md` file.
+    /// The chapter's Markdown content.
+    pub content: String,
+    /// The chapter's file, which for a section is its `README.md` and may
+    /// not exist.
     pub path: PathBuf,
+    /// The metadata from the top of the chapter's file.
+    pub front_matter: FrontMatter,
+    /// How many lines of the file the front matter takes, to report the
+    /// lines of `content` as lines of the file.
+    pub front_matter_lines: usize,
+    /// Where each line of `content` was written, once other files are
+    /// included in it.
+    #[serde(skip)]
+    pub source_lines: Vec<SourceLine>,
+    /// The chapters nested in this one, e.g. those of a section.
+    pub sub_items: Vec<BookItem>,
+    /// Whether the chapter is a work in progress, left out of the book
+    /// unless drafts are built.
+    pub draft: bool,
+    /// Whether the chapter is missing from the book's language and comes
+    /// from the library's default language instead.
+    pub fallback: bool,
 }
 
 impl Chapter {
     pub fn new<P: Into<PathBuf>>(
         name: &str,
+        content: String,
         path: P,
     ) -> Chapter {
         Chapter {
             name: name.to_string(),
+            content,
             path: path.into(),
+            front_matter: FrontMatter::default(),
+            front_matter_lines: 0,
+            source_lines: Vec::new(),
+            sub_items: Vec::new(),
+            draft: false,
+            fallback: false,
         }
     }
+
+    /// Whether the chapter is a section of the book, i.e. a directory whose
+    /// page is its optional `README.md`.
+    pub fn is_section(&self) -> bool {
+        self.path.file_name().is_some_and(|name| name == "README.md")
+    }
+}
+
+/// Where a line of Markdown was written, e.g. in a file included in a
+/// chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub path: PathBuf,
+    /// The line of the file, counted from 1.
+    pub line: usize,
+}
+
+impl SourceLine {
+    pub fn new<P: Into<PathBuf>>(path: P, line: usize) -> SourceLine {
+        SourceLine { path: path.into(), line }
+    }
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
 }
 
 /// A depth-first iterator over the items in a book.
 pub struct BookItems<'a> {
-    items: VecDeque<&'a Chapter>,
+    items: VecDeque<&'a BookItem>,
 }
 
 impl<'a> Iterator for BookItems<'a> {
-    type Item = &'a Chapter;
+    type Item = &'a BookItem;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.items.pop_front()
+        let item = self.items.pop_front();
+
+        if let Some(BookItem::Chapter(ch)) = item {
+            for sub_item in ch.sub_items.iter().rev() {
+                self.items.push_front(sub_item);
+            }
+        }
+
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A book in a temporary directory, made of the given files.
+    fn write_book(files: &[(&str, &str)]) -> TempDir {
+        let temp = TempDir::new().unwrap();
+        for (path, text) in files.iter() {
+            let path = temp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        temp
+    }
+
+    /// The chapters of a book as `name (path)` lines, indented by depth.
+    fn outline(items: &[BookItem], root: &Path, depth: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for item in items {
+            match item {
+                BookItem::Chapter(ch) => {
+                    let path = ch.path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+                    lines.push(format!("{}{} ({})", "  ".repeat(depth), ch.name, path));
+                    lines.extend(outline(&ch.sub_items, root, depth + 1));
+                }
+                BookItem::Separator => lines.push(format!("{}---", "  ".repeat(depth))),
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn load_sections_from_directories() {
+        let temp = write_book(&[
+            ("README.md", "# My book\n"),
+            ("intro.md", "# Introduction\n"),
+            ("part1/README.md", "# Part one\n"),
+            ("part1/setup.md", "# Setup\n"),
+            ("part1/deep/usage.md", "# Usage\n"),
+            ("part2/README.md", "---\norder: 1\n---\n# Part two\n"),
+            ("images/logo.png", ""),
+        ]);
+        let book = load_book(temp.path(), &Config::default()).unwrap();
+
+        assert_eq!(outline(&book.chapters, temp.path(), 0), vec![
+            "Part two (part2/README.md)",
+            "Introduction (intro.md)",
+            "Part one (part1/README.md)",
+            "  deep (part1/deep/README.md)",
+            "    Usage (part1/deep/usage.md)",
+            "  Setup (part1/setup.md)",
+        ]);
+
+        let sections: Vec<(&str, bool, &str)> = book.iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) => Some((ch.name.as_str(), ch.is_section(), ch.content.as_str())),
+                BookItem::Separator => None,
+            })
+            .collect();
+        assert_eq!(sections, vec![
+            ("Part two", true, "# Part two\n"),
+            ("Introduction", false, "# Introduction\n"),
+            ("Part one", true, "# Part one\n"),
+            ("deep", true, ""),
+            ("Usage", false, "# Usage\n"),
+            ("Setup", false, "# Setup\n"),
+        ]);
+    }
+
+    #[test]
+    fn load_sections_from_the_summary() {
+        let temp = write_book(&[
+            ("SUMMARY.md", "- [Setting up](part1)\n    - [Installing](part1/setup.md)\n\n---\n\n- [Intro](intro.md)\n- [Deep](part1/deep/README.md)\n"),
+            ("intro.md", "# Introduction\n"),
+            ("part1/README.md", "# Part one\n"),
+            ("part1/setup.md", "# Setup\n"),
+            ("part1/deep/usage.md", "# Usage\n"),
+        ]);
+        let book = load_book(temp.path(), &Config::default()).unwrap();
+
+        assert_eq!(outline(&book.chapters, temp.path(), 0), vec![
+            "Setting up (part1/README.md)",
+            "  Installing (part1/setup.md)",
+            "---",
+            "Intro (intro.md)",
+            "Deep (part1/deep/README.md)",
+        ]);
+    }
+
+    #[test]
+    fn reject_summaries_listing_other_files() {
+        let cases = ["- [Logo](images/logo.png)\n", "- [Missing](missing.md)\n", "- [Book](README.md)\n"];
+
+        for summary in cases.iter() {
+            let temp = write_book(&[("SUMMARY.md", summary), ("images/logo.png", "")]);
+            assert!(load_book(temp.path(), &Config::default()).is_err(), "with {:?}", summary);
+        }
+    }
+
+    #[test]
+    fn leave_out_draft_chapters() {
+        let temp = write_book(&[
+            ("README.md", "# My book\n"),
+            ("book.toml", "drafts = [\"listed.md\", \"part2\"]\n"),
+            ("intro.md", "# Introduction\n"),
+            ("_draft-named.md", "# Named\n"),
+            ("marked.md", "---\ndraft: true\n---\n# Marked\n"),
+            ("listed.md", "# Listed\n"),
+            ("part1/README.md", "# Part one\n"),
+            ("part1/_draft-wip/setup.md", "# Setup\n"),
+            ("part2/README.md", "# Part two\n"),
+            ("part2/usage.md", "# Usage\n"),
+        ]);
+        let book = load_book(temp.path(), &Config::default()).unwrap();
+
+        assert_eq!(outline(&book.chapters, temp.path(), 0), vec![
+            "Introduction (intro.md)",
+            "Part one (part1/README.md)",
+        ]);
+        assert!(!book.draft);
+
+        let mut config = Config::default();
+        config.build.drafts = true;
+        let book = load_book(temp.path(), &config).unwrap();
+
+        let drafts: Vec<(&str, bool)> = book.iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) => Some((ch.name.as_str(), ch.draft)),
+                BookItem::Separator => None,
+            })
+            .collect();
+        assert_eq!(drafts, vec![
+            ("Named", true),
+            ("Introduction", false),
+            ("Listed", true),
+            ("Marked", true),
+            ("Part one", false),
+            ("_draft-wip", true),
+            ("Setup", true),
+            ("Part two", true),
+            ("Usage", true),
+        ]);
+    }
+
+    #[test]
+    fn mark_draft_books() {
+        let cases = [
+            ("README.md", "---\ndraft: true\n---\n# My book\n"),
+            ("book.toml", "draft = true\n"),
+        ];
+
+        for (path, text) in cases.iter() {
+            let temp = write_book(&[(path, text)]);
+            assert!(is_draft_book(temp.path()).unwrap(), "with {:?}", path);
+            assert!(load_book(temp.path(), &Config::default()).unwrap().draft, "with {:?}", path);
+        }
+
+        let temp = write_book(&[("_draft-book/README.md", "# My book\n")]);
+        assert!(is_draft_book(&temp.path().join("_draft-book")).unwrap());
+        assert!(!is_draft_book(temp.path()).unwrap());
+    }
+
+    #[test]
+    fn add_the_untranslated_chapters() {
+        fn chapter(name: &str, path: PathBuf, sub_items: Vec<BookItem>) -> BookItem {
+            let mut chapter = Chapter::new(name, String::new(), path);
+            chapter.sub_items = sub_items;
+            BookItem::Chapter(chapter)
+        }
+
+        let en = Path::new("src/en/book");
+        let mut original = Book::new();
+        original.root = en.to_path_buf();
+        original.chapters = vec![
+            chapter("Intro", en.join("intro.md"), vec![]),
+            chapter("Part one", en.join("part1/README.md"), vec![
+                chapter("Setup", en.join("part1/setup.md"), vec![]),
+                chapter("Usage", en.join("part1/usage.md"), vec![]),
+            ]),
+            BookItem::Separator,
+            chapter("Outro", en.join("outro.md"), vec![]),
+        ];
+
+        let fr = Path::new("src/fr/book");
+        let mut book = Book::new();
+        book.root = fr.to_path_buf();
+        book.chapters = vec![
+            chapter("En plus", fr.join("extra.md"), vec![]),
+            chapter("Introduction", fr.join("intro.md"), vec![]),
+            chapter("Installation", fr.join("part1/setup.md"), vec![]),
+        ];
+
+        book.add_fallback_chapters(&original);
+
+        assert_eq!(outline(&book.chapters, fr, 0), vec![
+            "Introduction (intro.md)",
+            "Part one (part1/README.md)",
+            "  Installation (part1/setup.md)",
+            "  Usage (part1/usage.md)",
+            "---",
+            "Outro (outro.md)",
+            "En plus (extra.md)",
+        ]);
+
+        let fallbacks: Vec<&str> = book.iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) if ch.fallback => Some(ch.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fallbacks, vec!["Part one", "Usage", "Outro"]);
     }
 }
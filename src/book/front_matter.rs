@@ -0,0 +1,141 @@
+use serde_json::{Map, Value};
+
+use crate::errors::*;
+
+/// The optional metadata at the top of a chapter or `README.md`, either as
+/// YAML between `---` lines or as TOML between `+++` lines:
+///
+/// ```markdown
+/// ---
+/// title: Getting started
+/// order: 1
+/// tags: [intro, setup]
+/// ---
+///
+/// # Getting started
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrontMatter {
+    /// The title shown instead of the file's name.
+    pub title: Option<String>,
+    /// Where the chapter goes among the others when the book has no
+    /// `SUMMARY.md`, the lowest first.
+    pub order: Option<i64>,
+    /// A short description, used for the page's `<meta>` description.
+    pub description: Option<String>,
+    /// Whether the page is still a work in progress.
+    pub draft: bool,
+    pub tags: Vec<String>,
+    pub authors: Vec<String>,
+    /// Every other field, as is.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl FrontMatter {
+    /// Split the front matter from the Markdown content of a file, if it has
+    /// any.
+    pub fn split(text: &str) -> Result<(FrontMatter, &str)> {
+        // An editor may have saved a byte order mark
+        let text = text.trim_start_matches('\u{feff}');
+
+        let (delimiter, is_yaml) = if starts_with_line(text, "---") {
+            ("---", true)
+        } else if starts_with_line(text, "+++") {
+            ("+++", false)
+        } else {
+            return Ok((FrontMatter::default(), text));
+        };
+
+        let body_start = text.find('\n').expect("checked by starts_with_line") + 1;
+        let mut offset = body_start;
+        for line in text[body_start..].split_inclusive('\n') {
+            if line.trim_end() == delimiter {
+                let source = &text[body_start..offset];
+                let content = &text[offset + line.len()..];
+
+                let front_matter = if is_yaml {
+                    parse_yaml(source)?
+                } else {
+                    toml::from_str(source).chain_err(|| "Invalid TOML front matter")?
+                };
+                return Ok((front_matter, content));
+            }
+            offset += line.len();
+        }
+
+        bail!("The front matter has no closing `{}` line", delimiter)
+    }
+}
+
+fn parse_yaml(source: &str) -> Result<FrontMatter> {
+    // An empty document is null rather than an empty mapping
+    if source.trim().is_empty() {
+        return Ok(FrontMatter::default());
+    }
+
+    serde_yaml::from_str(source).chain_err(|| "Invalid YAML front matter")
+}
+
+fn starts_with_line(text: &str, delimiter: &str) -> bool {
+    text.split_inclusive('\n').next()
+        .is_some_and(|line| line.ends_with('\n') && line.trim_end() == delimiter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn front_matter(title: Option<&str>, order: Option<i64>, draft: bool, tags: &[&str]) -> FrontMatter {
+        FrontMatter {
+            title: title.map(String::from),
+            order,
+            draft,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn split_front_matter() {
+        let cases = [
+            ("# Title\n", front_matter(None, None, false, &[]), "# Title\n"),
+            ("---\ntitle: Intro\norder: 2\n---\n# Title\n", front_matter(Some("Intro"), Some(2), false, &[]), "# Title\n"),
+            ("---\ndraft: true\ntags: [a, b]\n---\n", front_matter(None, None, true, &["a", "b"]), ""),
+            ("+++\ntitle = \"Intro\"\norder = 1\n+++\n\ntext", front_matter(Some("Intro"), Some(1), false, &[]), "\ntext"),
+            ("---\n---\ntext", front_matter(None, None, false, &[]), "text"),
+            ("---\r\ntitle: Intro\r\n---\r\ntext", front_matter(Some("Intro"), None, false, &[]), "text"),
+            ("\u{feff}---\ntitle: Intro\n---\ntext", front_matter(Some("Intro"), None, false, &[]), "text"),
+            ("---", front_matter(None, None, false, &[]), "---"),
+            ("text\n---\ntitle: Intro\n---\n", front_matter(None, None, false, &[]), "text\n---\ntitle: Intro\n---\n"),
+            ("----\ntitle: Intro\n----\n", front_matter(None, None, false, &[]), "----\ntitle: Intro\n----\n"),
+        ];
+
+        for (text, expected, content) in cases.iter() {
+            let (found, rest) = FrontMatter::split(text).unwrap();
+            assert_eq!((&found, rest), (expected, *content), "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn keep_extra_fields() {
+        let (found, _) = FrontMatter::split("---\nlayout: wide\n---\n").unwrap();
+        assert_eq!(found.extra.get("layout"), Some(&Value::from("wide")));
+    }
+
+    #[test]
+    fn reject_invalid_front_matter() {
+        let cases = [
+            "---\ntitle: Intro\n",
+            "+++\ntitle = \"Intro\"\n---\n",
+            "---\ntitle: [Intro\n---\n",
+            "+++\ntitle = Intro\n+++\n",
+            "---\norder: first\n---\n",
+        ];
+
+        for text in cases.iter() {
+            assert!(FrontMatter::split(text).is_err(), "in {:?}", text);
+        }
+    }
+}
@@ -1,66 +1,184 @@
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
 
-use serde_json::to_string;
 use walkdir::DirEntry;
 
+use crate::config::{Config, CONFIG_FILE};
 use crate::errors::*;
-use crate::render::{make_book_data, render_book, render_summary, RenderContext};
+use crate::preprocess::{CmdPreprocessor, IncludePreprocessor, LinkPreprocessor, Preprocessor,
+                        PreprocessorContext};
+use crate::render::{CmdRenderer, HtmlRenderer, RenderContext, Renderer};
 use crate::utils::{remove_dir_content, write_file};
 
 pub use self::book::*;
+pub use self::front_matter::FrontMatter;
 pub use self::summary::*;
 
 mod book;
+mod front_matter;
 mod summary;
 
-pub fn build<P: AsRef<Path>>(root_dir: P) -> Result<()> {
+pub fn build<P: AsRef<Path>>(root_dir: P, config: &Config) -> Result<()> {
+    build_with(root_dir, config, Vec::new(), Vec::new())
+}
+
+/// Build the library with every preprocessor and renderer listed in the
+/// config, looking them up among the given ones first and then among the
+/// built-in ones.
+///
+/// A multilingual library is built one language after the other, the
+/// chapters missing from a language falling back to the default one.
+pub fn build_with<P: AsRef<Path>>(root_dir: P,
+                                  config: &Config,
+                                  preprocessors: Vec<Box<dyn Preprocessor>>,
+                                  renderers: Vec<Box<dyn Renderer>>) -> Result<()> {
+    let root = root_dir.as_ref().to_path_buf();
+
     // clear build-dir files
-    let build_dir = get_build_dir(&root_dir.as_ref().to_path_buf());
+    let build_dir = get_build_dir(&root, config);
     if build_dir.exists() {
         remove_dir_content(build_dir.as_path())?;
     }
 
-    let mut summary = load_summary(root_dir.as_ref()).unwrap();
-    debug!("{:#?}", summary);
+    let mut preprocessors = preprocessors;
+    let preprocessors = config.preprocessors().iter()
+        .map(|name| {
+            let position = preprocessors.iter().position(|p| p.name() == name);
+            match position {
+                Some(position) => Ok(preprocessors.remove(position)),
+                None => builtin_preprocessor(name, config),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut renderers = renderers;
+    let renderers = config.renderers().iter()
+        .map(|name| {
+            let position = renderers.iter().position(|r| r.name() == name);
+            match position {
+                Some(position) => Ok(renderers.remove(position)),
+                None => builtin_renderer(name, config),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if config.library.languages.is_empty() {
+        build_language(&root, config, &preprocessors, &renderers, None)?;
+        return Ok(());
+    }
 
-    let mut data = String::new();
-    summary.for_each_mut(|e| {
-        if e.is_book {
-            let path = e.path.join("README.html").display()
-                .to_string().replace("src", "build");
-            data.push_str(
-                format!("{:width$}- [{name}]({path})\n", "",
-                        width = e.level * 2, name = e.name, path = path).as_str());
-        } else {
-            data.push_str(
-                format!("{:width$}- {name}\n", "",
-                        width = e.level * 2, name = e.name).as_str());
+    for language in config.library.languages.iter() {
+        let dir = get_language_dir(&root, language, config);
+        if !dir.is_dir() {
+            bail!("There is no {} directory for the books in {}", dir.display(), language);
         }
-    });
+    }
+
+    let default = config.for_language(config.default_language());
+    info!("Building the {} books", config.default_language());
+    let originals = build_language(&root, &default, &preprocessors, &renderers, None)?;
 
-    render_summary(data.as_str(),
-                   &get_build_dir(&root_dir.as_ref().to_path_buf()))?;
+    for language in config.library.languages.iter().skip(1) {
+        info!("Building the {} books", language);
+        let config = config.for_language(language);
+        let fallback = (get_language_dir(&root, config.default_language(), &config), &originals[..]);
+        build_language(&root, &config, &preprocessors, &renderers, Some(fallback))?;
+    }
 
-    trace!("--------------------\n");
+    Ok(())
+}
 
+/// Build the books of the config's `library.language`, completing them with
+/// the chapters of the `fallback` books of the given directory, and return
+/// them once preprocessed.
+fn build_language(root: &Path,
+                  config: &Config,
+                  preprocessors: &[Box<dyn Preprocessor>],
+                  renderers: &[Box<dyn Renderer>],
+                  fallback: Option<(PathBuf, &[Book])>) -> Result<Vec<Book>> {
+    let src_dir = get_books_dir(root, config);
+    let summary = load_summary(root, config)?;
+    debug!("{:#?}", summary);
+
+    let preprocess_ctx = PreprocessorContext::new(root, config.clone(), summary.clone());
+
+    let mut books = Vec::new();
     for entry in summary.iter().filter(|e| e.is_book) {
         trace!("{:#?}\n~~~~~~~~~~~~~~\n", entry);
-        let book = load_book(entry.path.as_path()).unwrap();
+        let relative = entry.path.strip_prefix(&src_dir)
+            .expect("Books are always inside the source directory");
+        let mut book = load_book(entry.path.as_path(), config)?;
+        // The summary may give the book another display title
+        book.name = entry.name.clone();
+        // and it may be in a draft category
+        book.draft = book.draft || has_draft_name(relative);
+
+        for preprocessor in preprocessors.iter() {
+            debug!("Running the {} preprocessor on {}", preprocessor.name(), book.name);
+            book = preprocessor.run(&preprocess_ctx, book)
+                .chain_err(|| format!("The {} preprocessor failed on {}",
+                                      preprocessor.name(), entry.path.display()))?;
+        }
+
+        if let Some((ref fallback_dir, originals)) = fallback {
+            let original = originals.iter().find(|original| original.root == fallback_dir.join(relative));
+            if let Some(original) = original {
+                book.add_fallback_chapters(original);
+            }
+        }
         debug!("{:#?}\n~~~~~~~~~~~~~~\n", book);
 
-        render_book(&RenderContext::new(
-            root_dir.as_ref().to_path_buf(),
-            book.clone(),
-            build_dir.clone(),
-        ))?;
+        books.push(book);
     }
 
-    Ok(())
+    for renderer in renderers {
+        let name = renderer.name();
+        info!("Running the {} backend", name);
+        let destination = get_renderer_dir(root, config, name)
+            .join(config.language_dir(&config.library.language));
+        let ctx = RenderContext::new(
+            root,
+            summary.clone(),
+            books.clone(),
+            config.clone(),
+            destination,
+        );
+
+        renderer.render(&ctx)
+            .chain_err(|| format!("Rendering failed with the {} backend", name))?;
+    }
+
+    Ok(books)
+}
+
+fn builtin_preprocessor(name: &str, config: &Config) -> Result<Box<dyn Preprocessor>> {
+    let key = format!("preprocessor.{}.command", name);
+    if let Some(cmd) = config.get_deserialized_opt::<String>(&key)? {
+        return Ok(Box::new(CmdPreprocessor::new(name.to_string(), cmd)));
+    }
+
+    match name {
+        IncludePreprocessor::NAME => Ok(Box::new(IncludePreprocessor::new())),
+        LinkPreprocessor::NAME => Ok(Box::new(LinkPreprocessor::new())),
+        _ => bail!("There is no preprocessor called `{}`, set `{}` to run a command", name, key),
+    }
+}
+
+fn builtin_renderer(name: &str, config: &Config) -> Result<Box<dyn Renderer>> {
+    let key = format!("output.{}.command", name);
+    if let Some(cmd) = config.get_deserialized_opt::<String>(&key)? {
+        return Ok(Box::new(CmdRenderer::new(name.to_string(), cmd)));
+    }
+
+    match name {
+        "html" => Ok(Box::new(HtmlRenderer::new())),
+        _ => bail!("There is no renderer called `{}`, set `{}` to run a command", name, key),
+    }
 }
 
 pub fn init<P: AsRef<Path>>(root_dir: P) -> Result<()> {
-    let src_dir = get_books_dir(&root_dir.as_ref().to_path_buf());
+    let config = Config::default();
+    let src_dir = get_books_dir(root_dir.as_ref(), &config);
     if !src_dir.exists() {
         create_dir_all(src_dir.as_path())?;
 
@@ -69,15 +187,45 @@ pub fn init<P: AsRef<Path>>(root_dir: P) -> Result<()> {
         write_file(root_dir.as_ref(),
                    ".gitignore", data.as_bytes())?;
     }
+
+    if !root_dir.as_ref().join(CONFIG_FILE).exists() {
+        debug!("Creating {}", CONFIG_FILE);
+        let data = toml::to_string(&config)
+            .chain_err(|| "Unable to serialize the default config")?;
+        write_file(root_dir.as_ref(), CONFIG_FILE, data.as_bytes())?;
+    }
     Ok(())
 }
 
-pub fn get_books_dir(root: &PathBuf) -> PathBuf {
-    root.join("src")
+/// The source directory, holding the books or, in a multilingual library,
+/// a directory of books for each language.
+pub fn get_src_dir(root: &Path, config: &Config) -> PathBuf {
+    root.join(&config.library.src)
+}
+
+/// Where the books are: the source directory, or the subdirectory of the
+/// language being built in a multilingual library.
+pub fn get_books_dir(root: &Path, config: &Config) -> PathBuf {
+    if config.library.languages.is_empty() {
+        get_src_dir(root, config)
+    } else {
+        get_language_dir(root, &config.library.language, config)
+    }
+}
+
+/// Where the books of a language of a multilingual library are.
+pub fn get_language_dir(root: &Path, language: &str, config: &Config) -> PathBuf {
+    get_src_dir(root, config).join(language)
+}
+
+pub fn get_build_dir(root: &Path, config: &Config) -> PathBuf {
+    root.join(&config.build.build_dir)
 }
 
-pub fn get_build_dir(root: &PathBuf) -> PathBuf {
-    root.join("build")
+/// Where a renderer writes to: the subdirectory of the build directory
+/// named after it, e.g. `build/html`.
+pub fn get_renderer_dir(root: &Path, config: &Config, name: &str) -> PathBuf {
+    get_build_dir(root, config).join(name)
 }
 
 pub fn is_hidden(entry: &DirEntry) -> bool {
@@ -1,18 +1,50 @@
-use std::borrow::{Borrow, BorrowMut};
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-use walkdir::{DirEntry, WalkDir};
+use pulldown_cmark::{Event, Parser, Tag};
+use walkdir::WalkDir;
 
-use crate::book::{get_books_dir, is_hidden};
+use crate::book::{book_name, get_books_dir, has_draft_name, is_draft_book, is_hidden, load_book_config};
+use crate::config::Config;
 use crate::errors::*;
 
-pub fn load_summary<P: AsRef<Path>>(root_dir: P) -> Result<Summary> {
+/// The name of the optional file setting the order of books and chapters.
+pub const SUMMARY_FILE: &str = "SUMMARY.md";
+
+pub fn load_summary<P: AsRef<Path>>(root_dir: P, config: &Config) -> Result<Summary> {
     debug!("root dir: {:?}", root_dir.as_ref());
-    let src_dir = get_books_dir(&root_dir.as_ref().to_path_buf());
-    let books = make_summary(src_dir.as_path(), 0)?;
+    let src_dir = get_books_dir(root_dir.as_ref(), config);
+    let mut title = Some(String::from(src_dir.to_path_buf().to_str().unwrap()));
+
+    let summary_file = src_dir.join(SUMMARY_FILE);
+    let mut books = if summary_file.exists() {
+        debug!("Ordering books by {}", summary_file.display());
+        let parsed = parse_summary_file(&summary_file)?;
+        if parsed.title.is_some() {
+            title = parsed.title;
+        }
+
+        let mut listed = HashSet::new();
+        let books = make_summary_from_entries(src_dir.as_path(), &parsed.entries, 0, &mut listed)
+            .chain_err(|| format!("Unable to load {}", summary_file.display()))?;
+
+        let walked = Summary { items: make_summary(src_dir.as_path(), 0)?, ..Default::default() };
+        let unlisted = walked.iter()
+            .filter(|l| l.is_book && !listed.contains(&l.path))
+            .filter(|l| !has_draft_name(l.path.strip_prefix(&src_dir).unwrap_or(&l.path)));
+        for link in unlisted {
+            warn!("{} is not listed in {}, skipping it", link.path.display(), summary_file.display());
+        }
 
-    let title = Some(String::from(src_dir.to_path_buf().to_str().unwrap()));
+        books
+    } else {
+        make_summary(src_dir.as_path(), 0)?
+    };
+
+    if !config.build.drafts {
+        remove_drafts(&mut books, &src_dir)?;
+    }
 
     Ok(Summary {
         title,
@@ -21,8 +53,11 @@ pub fn load_summary<P: AsRef<Path>>(root_dir: P) -> Result<Summary> {
     })
 }
 
-fn make_summary<P: AsRef<Path>>(src_dir: P, level: usize) -> Result<Vec<Link>> {
-    let walker = WalkDir::new(src_dir.as_ref()).max_depth(1).into_iter();
+fn make_summary<P: AsRef<Path>>(src_dir: P, level: usize) -> Result<Vec<SummaryItem>> {
+    let walker = WalkDir::new(src_dir.as_ref())
+        .max_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter();
 
     let mut books = Vec::new();
 
@@ -35,20 +70,243 @@ fn make_summary<P: AsRef<Path>>(src_dir: P, level: usize) -> Result<Vec<Link>> {
             trace!("{} - {}", depth, entry.path().display());
 
             let path = entry.path().to_str().unwrap();
+            let is_book = entry.path().join("README.md").exists();
+
+            // The subdirectories of a book are its sections
+            let (name, nested_items) = if is_book {
+                (book_name(entry.path())?, Vec::new())
+            } else {
+                (filename.to_string(), make_summary(PathBuf::from(path), level + 1)?)
+            };
 
-            books.push(Link {
-                name: filename.into(),
+            let order = if is_book { load_book_config(entry.path())?.book.order } else { None };
+
+            books.push((order, SummaryItem::Link(Link {
+                name,
                 level,
-                is_book: entry.path().join("README.md").exists(),
+                is_book,
                 path: PathBuf::from(path),
-                nested_items: make_summary(PathBuf::from(path), level + 1).unwrap(),
-            });
+                nested_items,
+            })));
+        }
+    }
+
+    // Books with an `order` go first, the others keep their filename order
+    books.sort_by_key(|&(order, _)| order.map_or((1, 0), |order| (0, order)));
+
+    Ok(books.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Leave the draft books out of the tree, recursively, along with those of
+/// the categories named as drafts.
+fn remove_drafts(items: &mut Vec<SummaryItem>, src_dir: &Path) -> Result<()> {
+    let mut kept = Vec::with_capacity(items.len());
+
+    for mut item in items.drain(..) {
+        if let SummaryItem::Link(ref mut link) = item {
+            // A book listed by the summary may be inside a draft directory
+            let relative = link.path.strip_prefix(src_dir).unwrap_or(&link.path);
+            if has_draft_name(relative) || link.is_book && is_draft_book(&link.path)? {
+                let kind = if link.is_book { "book" } else { "category" };
+                warn!("Skipping the draft {} {}", kind, link.path.display());
+                continue;
+            }
+            remove_drafts(&mut link.nested_items, src_dir)?;
+        }
+        kept.push(item);
+    }
+
+    *items = kept;
+    Ok(())
+}
+
+/// Build the books' tree from the entries of the library's `SUMMARY.md`,
+/// recording every directory it lists.
+fn make_summary_from_entries(src_dir: &Path,
+                             entries: &[SummaryEntry],
+                             level: usize,
+                             listed: &mut HashSet<PathBuf>) -> Result<Vec<SummaryItem>> {
+    let mut books = Vec::new();
+
+    for entry in entries {
+        let link = match entry {
+            SummaryEntry::Link(link) => link,
+            SummaryEntry::Separator => {
+                books.push(SummaryItem::Separator);
+                continue;
+            }
+        };
+
+        let mut path = src_dir.join(&link.location);
+        if path.file_name().is_some_and(|name| name == "README.md") {
+            path.pop();
+        }
+
+        if !path.is_dir() {
+            bail!(ErrorKind::ParseError(
+                link.line, link.col,
+                format!("{} is not a book or category directory", link.location.display())));
         }
+
+        listed.insert(path.clone());
+
+        books.push(SummaryItem::Link(Link {
+            name: link.title.clone(),
+            level,
+            is_book: path.join("README.md").exists(),
+            nested_items: make_summary_from_entries(
+                src_dir, &link.nested_items, level + 1, listed)?,
+            path,
+        }));
     }
 
     Ok(books)
 }
 
+/// The parsed content of a `SUMMARY.md` file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SummaryFile {
+    /// The text of the first heading, if any.
+    pub title: Option<String>,
+    /// The list items and separators, in order.
+    pub entries: Vec<SummaryEntry>,
+}
+
+/// An entry of a `SUMMARY.md` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummaryEntry {
+    /// A list item linking to a book, category or chapter.
+    Link(SummaryLink),
+    /// A horizontal rule (`---`) between list items.
+    Separator,
+}
+
+/// A list item of a `SUMMARY.md` file, e.g. `- [Title](location)`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SummaryLink {
+    /// The text of the link, displayed instead of the file name.
+    pub title: String,
+    /// Where the link points to, relative to the `SUMMARY.md` file.
+    pub location: PathBuf,
+    /// The line the item starts on, for error reporting.
+    pub line: usize,
+    /// The column the item starts on, for error reporting.
+    pub col: usize,
+    /// The items of a list nested in this one.
+    pub nested_items: Vec<SummaryEntry>,
+}
+
+/// Read and parse a `SUMMARY.md` file.
+pub fn parse_summary_file<P: AsRef<Path>>(path: P) -> Result<SummaryFile> {
+    let text = read_to_string(path.as_ref())?;
+    parse_summary(&text)
+        .chain_err(|| format!("Unable to parse {}", path.as_ref().display()))
+}
+
+/// Parse the content of a `SUMMARY.md` file.
+///
+/// The first heading is the title, every list item must be a link, nested
+/// lists nest their items and `---` lines become separators. Any other
+/// content is ignored.
+pub fn parse_summary(text: &str) -> Result<SummaryFile> {
+    let mut title = None;
+    let mut heading: Option<String> = None;
+    // The entries of the lists currently open, the outermost level first.
+    let mut lists: Vec<Vec<SummaryEntry>> = vec![Vec::new()];
+    // The list items currently open, with the offset they start at.
+    let mut items: Vec<(usize, Option<SummaryLink>)> = Vec::new();
+    let mut in_link = false;
+
+    for (event, range) in Parser::new(text).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Header(_)) if items.is_empty() => heading = Some(String::new()),
+            Event::End(Tag::Header(_)) if items.is_empty() => {
+                let text = heading.take();
+                if title.is_none() {
+                    title = text.map(|t| t.trim().to_string());
+                }
+            }
+            Event::Start(Tag::List(_)) => lists.push(Vec::new()),
+            Event::End(Tag::List(_)) => {
+                let entries = lists.pop().expect("lists are balanced");
+                match items.last_mut() {
+                    Some((_, Some(link))) => link.nested_items.extend(entries),
+                    Some((start, None)) => {
+                        let (line, col) = line_col(text, *start);
+                        bail!(ErrorKind::ParseError(
+                            line, col, "A list item with a nested list must be a link".into()));
+                    }
+                    None => lists.last_mut().expect("lists are balanced").extend(entries),
+                }
+            }
+            Event::Start(Tag::Item) => {
+                // The range of a nested item may start within its indentation
+                let indent = text[range.start..].len() - text[range.start..].trim_start_matches(' ').len();
+                items.push((range.start + indent, None))
+            }
+            Event::End(Tag::Item) => {
+                let (start, link) = items.pop().expect("items are balanced");
+                let (line, col) = line_col(text, start);
+                let link = match link {
+                    Some(link) => link,
+                    None => bail!(ErrorKind::ParseError(
+                        line, col, "Expected a link like `[Title](location)`".into())),
+                };
+
+                if link.title.trim().is_empty() {
+                    bail!(ErrorKind::ParseError(line, col, "The link has no title".into()));
+                }
+
+                lists.last_mut().expect("lists are balanced").push(SummaryEntry::Link(link));
+            }
+            Event::Start(Tag::Link(_, dest, _)) => {
+                if let Some((start, link @ None)) = items.last_mut() {
+                    let (line, col) = line_col(text, *start);
+                    if dest.is_empty() || dest.contains("://") || dest.starts_with('/') {
+                        bail!(ErrorKind::ParseError(
+                            line, col, format!("`{}` is not a relative path", dest)));
+                    }
+
+                    *link = Some(SummaryLink {
+                        location: PathBuf::from(dest.as_ref()),
+                        line,
+                        col,
+                        ..Default::default()
+                    });
+                    in_link = true;
+                }
+            }
+            Event::End(Tag::Link(..)) => in_link = false,
+            Event::Text(t) | Event::Code(t) => {
+                if in_link {
+                    if let Some((_, Some(link))) = items.last_mut() {
+                        link.title.push_str(&t);
+                    }
+                } else if let Some(heading) = heading.as_mut() {
+                    heading.push_str(&t);
+                }
+            }
+            Event::Start(Tag::Rule) => {
+                lists.last_mut().expect("lists are balanced").push(SummaryEntry::Separator)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(SummaryFile {
+        title,
+        entries: lists.pop().unwrap_or_default(),
+    })
+}
+
+/// Turn a byte offset into a one-based line and column.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, col)
+}
+
 
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Summary {
@@ -57,7 +315,7 @@ pub struct Summary {
     /// Books' root directory.
     pub root: PathBuf,
     /// Books
-    pub items: Vec<Link>,
+    pub items: Vec<SummaryItem>,
 }
 
 impl Summary {
@@ -69,7 +327,7 @@ impl Summary {
     /// Get a depth-first iterator over the items in the book.
     pub fn iter(&self) -> Books<'_> {
         Books {
-            items: self.items.iter().collect(),
+            items: links(&self.items).collect(),
         }
     }
 
@@ -84,24 +342,36 @@ impl Summary {
 pub fn for_each_mut<'a, F, I>(func: &mut F, items: I)
     where
         F: FnMut(&mut Link),
-        I: IntoIterator<Item=&'a mut Link>,
+        I: IntoIterator<Item=&'a mut SummaryItem>,
 {
     for item in items {
-        func(item);
-        for_each_mut(func, &mut item.nested_items);
+        if let SummaryItem::Link(link) = item {
+            func(link);
+            for_each_mut(func, &mut link.nested_items);
+        }
     }
 }
 
+/// An item of the books' tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SummaryItem {
+    /// A book or a category of books.
+    Link(Link),
+    /// A separator between books or categories.
+    Separator,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Link {
-    /// The name of the book.
+    /// The name of the book, from the `SUMMARY.md` or the book's
+    /// `README.md`, or of the category's directory.
     pub name: String,
     pub is_book: bool,
     /// The location of the book's source file, retaking the book's `src`
     /// directory as the root.
     pub path: PathBuf,
     pub level: usize,
-    pub nested_items: Vec<Link>,
+    pub nested_items: Vec<SummaryItem>,
 }
 
 impl Link {
@@ -130,6 +400,14 @@ impl Default for Link {
 }
 
 
+/// The links among some items, leaving out the separators.
+fn links(items: &[SummaryItem]) -> impl DoubleEndedIterator<Item=&Link> {
+    items.iter().filter_map(|item| match item {
+        SummaryItem::Link(link) => Some(link),
+        SummaryItem::Separator => None,
+    })
+}
+
 /// A depth-first iterator over the items in a book.
 pub struct Books<'a> {
     items: VecDeque<&'a Link>,
@@ -143,7 +421,7 @@ impl<'a> Iterator for Books<'a> {
 
         if let Some(link) = item {
             // if we wanted a breadth-first iterator we'd `extend()` here
-            for sub_item in link.nested_items.iter().rev() {
+            for sub_item in links(&link.nested_items).rev() {
                 self.items.push_front(sub_item);
             }
         }
@@ -153,3 +431,131 @@ impl<'a> Iterator for Books<'a> {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn link(title: &str, location: &str, line: usize, col: usize, nested_items: Vec<SummaryEntry>) -> SummaryEntry {
+        SummaryEntry::Link(SummaryLink {
+            title: title.to_string(),
+            location: PathBuf::from(location),
+            line,
+            col,
+            nested_items,
+        })
+    }
+
+    #[test]
+    fn parse_summaries() {
+        let cases = [
+            ("", None, vec![]),
+            ("# Library\n", Some("Library"), vec![]),
+            ("# The *Big* Library\n\n# Other\n", Some("The Big Library"), vec![]),
+            ("- [One](one)\n- [Two](two/)\n",
+             None,
+             vec![link("One", "one", 1, 1, vec![]), link("Two", "two/", 2, 1, vec![])]),
+            ("# Books\n\n- [`Rust` book](rust)\n",
+             Some("Books"),
+             vec![link("Rust book", "rust", 3, 1, vec![])]),
+            ("- [Cat](cat)\n    - [A](cat/a)\n    - [B](cat/b)\n- [C](c)\n",
+             None,
+             vec![link("Cat", "cat", 1, 1, vec![link("A", "cat/a", 2, 5, vec![]), link("B", "cat/b", 3, 5, vec![])]),
+                  link("C", "c", 4, 1, vec![])]),
+            ("- [A](a)\n\n---\n\n- [B](b)\n",
+             None,
+             vec![link("A", "a", 1, 1, vec![]), SummaryEntry::Separator, link("B", "b", 5, 1, vec![])]),
+            ("Some text.\n\n- [A](a)\n", None, vec![link("A", "a", 3, 1, vec![])]),
+        ];
+
+        for (text, title, entries) in cases.iter() {
+            let parsed = parse_summary(text).unwrap();
+            assert_eq!(parsed.title.as_deref(), *title, "in {:?}", text);
+            assert_eq!(&parsed.entries, entries, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_summaries() {
+        let cases = [
+            ("- one\n", (1, 1, "Expected a link like `[Title](location)`")),
+            ("- [A](a)\n- b\n", (2, 1, "Expected a link like `[Title](location)`")),
+            ("- [](a)\n", (1, 1, "The link has no title")),
+            ("- [A](https://x.com)\n", (1, 1, "`https://x.com` is not a relative path")),
+            ("- [A](/a)\n", (1, 1, "`/a` is not a relative path")),
+            ("- A\n    - [B](b)\n", (1, 1, "A list item with a nested list must be a link")),
+        ];
+
+        for (text, expected) in cases.iter() {
+            match parse_summary(text).map_err(|e| e.0) {
+                Err(ErrorKind::ParseError(line, col, message)) => {
+                    assert_eq!((line, col, message.as_str()), *expected, "in {:?}", text)
+                }
+                other => panic!("expected a parse error in {:?}, got {:?}", text, other),
+            }
+        }
+    }
+
+    /// A library in a temporary directory, made of the given files of its
+    /// `src` directory.
+    fn write_library(files: &[(&str, &str)]) -> TempDir {
+        let temp = TempDir::new().unwrap();
+        for (path, text) in files.iter() {
+            let path = temp.path().join("src").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        temp
+    }
+
+    fn book_paths(summary: &Summary) -> Vec<String> {
+        summary.iter()
+            .filter(|link| link.is_book)
+            .map(|link| {
+                let relative = link.path.strip_prefix(summary.root.join("src")).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn leave_out_draft_books() {
+        let files = [
+            ("cat/book1/README.md", "# Book one\n"),
+            ("cat/_draft-book/README.md", "# Named draft\n"),
+            ("cat/marked/README.md", "---\ndraft: true\n---\n# Marked draft\n"),
+            ("cat/configured/README.md", "# Configured draft\n"),
+            ("cat/configured/book.toml", "draft = true\n"),
+            ("_draft-cat/secret/README.md", "# Secret\n"),
+        ];
+        let temp = write_library(&files);
+
+        let summary = load_summary(temp.path(), &Config::default()).unwrap();
+        assert_eq!(book_paths(&summary), vec!["cat/book1"]);
+
+        let mut config = Config::default();
+        config.build.drafts = true;
+        let summary = load_summary(temp.path(), &config).unwrap();
+        assert_eq!(book_paths(&summary), vec![
+            "_draft-cat/secret",
+            "cat/_draft-book",
+            "cat/book1",
+            "cat/configured",
+            "cat/marked",
+        ]);
+    }
+
+    #[test]
+    fn leave_out_listed_books_in_draft_directories() {
+        let temp = write_library(&[
+            ("SUMMARY.md", "- [One](cat/book1)\n- [Secret](_draft-cat/secret)\n"),
+            ("cat/book1/README.md", "# Book one\n"),
+            ("_draft-cat/secret/README.md", "# Secret\n"),
+        ]);
+
+        let summary = load_summary(temp.path(), &Config::default()).unwrap();
+        assert_eq!(book_paths(&summary), vec!["cat/book1"]);
+    }
+}
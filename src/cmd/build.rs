@@ -1,9 +1,10 @@
 use clap::{App, ArgMatches, SubCommand};
 
 use jungle::book::*;
+use jungle::config::load_config;
 use jungle::errors::*;
 
-use crate::cmd::{get_root_dir, open};
+use crate::cmd::{get_root_dir, open_index};
 
 pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
     SubCommand::with_name("build")
@@ -14,17 +15,23 @@ pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
              (Defaults to the Current Directory when omitted)'",
         )
         .arg_from_usage("-o, --open 'Opens the compiled book in a web browser'")
+        .arg_from_usage("--drafts 'Includes the books and chapters marked as drafts'")
 }
 
 pub fn execute(args: &ArgMatches) -> Result<()> {
     let root = get_root_dir(args);
     trace!("root dir: {:?}", root);
 
-    if get_books_dir(&root).exists() {
-        build(root.as_path())?;
+    let mut config = load_config(&root)?;
+    if args.is_present("drafts") {
+        config.build.drafts = true;
+    }
+
+    if get_src_dir(&root, &config).exists() {
+        build(root.as_path(), &config)?;
 
         if args.is_present("open") {
-            open(get_build_dir(&root).join("index.html"));
+            open_index(&root, &config);
         }
     }
 
@@ -4,8 +4,13 @@ use std::path::{Path, PathBuf};
 
 use clap::ArgMatches;
 
+use jungle::book::get_renderer_dir;
+use jungle::config::Config;
+
 pub mod init;
 pub mod build;
+pub mod serve;
+pub mod watch;
 
 fn get_root_dir(args: &ArgMatches) -> PathBuf {
     if let Some(dir) = args.value_of("dir") {
@@ -26,3 +31,21 @@ fn open<P: AsRef<OsStr>>(path: P) {
         error!("Error opening web browser: {}", e);
     }
 }
+
+/// Where the HTML renderer writes to, or `None` when it isn't among the
+/// configured renderers.
+fn get_html_dir(root: &Path, config: &Config) -> Option<PathBuf> {
+    if config.renderers().iter().any(|name| name == "html") {
+        Some(get_renderer_dir(root, config, "html"))
+    } else {
+        None
+    }
+}
+
+/// Open the library's index in a web browser, when there's HTML output.
+fn open_index(root: &Path, config: &Config) {
+    match get_html_dir(root, config) {
+        Some(html_dir) => open(html_dir.join("index.html")),
+        None => warn!("There is no HTML output to open, `html` isn't among the renderers"),
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use percent_encoding::percent_decode_str;
+use tiny_http::{Header, Request, Response, Server};
+
+use jungle::book::*;
+use jungle::config::load_config;
+use jungle::errors::*;
+use jungle::utils;
+
+use crate::cmd::{get_html_dir, get_root_dir, open};
+use crate::cmd::watch::trigger_on_change;
+
+/// Injected into every served HTML page; reloads the page when the websocket
+/// announces a successful rebuild.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script type="text/javascript">
+    (function () {
+        var socket = new WebSocket("ws://" + window.location.hostname + ":{{port}}");
+        socket.onmessage = function (event) {
+            if (event.data === "reload") {
+                socket.close();
+                location.reload();
+            }
+        };
+
+        window.onbeforeunload = function () {
+            socket.close();
+        };
+    })();
+</script>"#;
+
+/// A websocket connection that only ever receives reload notifications.
+struct ReloadClient;
+
+impl ws::Handler for ReloadClient {}
+
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("serve")
+        .about("Serves books at http://localhost:3000, and rebuilds them on changes")
+        // the {n} denotes a newline which will properly aligned in all help messages
+        .arg_from_usage(
+            "[dir] 'Root directory for the book{n}\
+             (Defaults to the Current Directory when omitted)'",
+        )
+        .arg(
+            Arg::with_name("hostname")
+                .short("n")
+                .long("hostname")
+                .takes_value(true)
+                .default_value("localhost")
+                .empty_values(false)
+                .help("Hostname to listen on for HTTP connections"),
+        )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .takes_value(true)
+                .default_value("3000")
+                .empty_values(false)
+                .help("Port to use for HTTP connections"),
+        )
+        .arg(
+            Arg::with_name("websocket-port")
+                .short("w")
+                .long("websocket-port")
+                .takes_value(true)
+                .default_value("3001")
+                .empty_values(false)
+                .help("Port to use for the live reload websocket"),
+        )
+        .arg_from_usage("-o, --open 'Opens the book server in a web browser'")
+}
+
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    let root = get_root_dir(args);
+    trace!("root dir: {:?}", root);
+
+    let hostname = args.value_of("hostname").unwrap();
+    let port = args.value_of("port").unwrap();
+    let ws_port = args.value_of("websocket-port").unwrap();
+
+    let address = format!("{}:{}", hostname, port);
+    let ws_address = format!("{}:{}", hostname, ws_port);
+
+    let config = load_config(&root)?;
+    let build_dir = get_html_dir(&root, &config)
+        .ok_or("There is nothing to serve without the `html` renderer, add an `[output.html]` table")?;
+    build(root.as_path(), &config)?;
+
+    let server = Server::http(address.as_str())
+        .map_err(|e| format!("Unable to listen on {}: {}", address, e))?;
+
+    let ws_server = ws::WebSocket::new(|_| ReloadClient)
+        .chain_err(|| "Unable to create the live reload websocket")?;
+    let broadcaster = ws_server.broadcaster();
+
+    thread::spawn(move || {
+        if let Err(e) = ws_server.listen(ws_address.as_str()) {
+            error!("Unable to listen on {}: {}", ws_address, e);
+        }
+    });
+
+    let script = LIVE_RELOAD_SCRIPT.replace("{{port}}", ws_port);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            serve_file(request, &build_dir, &script);
+        }
+    });
+
+    let serving_url = format!("http://{}", address);
+    info!("Serving on: {}", serving_url);
+
+    if args.is_present("open") {
+        open(serving_url);
+    }
+
+    trigger_on_change(root.as_path(), move |paths, root| {
+        info!("Files changed: {:?}", paths);
+        info!("Building books...");
+
+        match load_config(root).and_then(|config| build(root, &config)) {
+            Ok(()) => {
+                let _ = broadcaster.send("reload");
+            }
+            Err(e) => utils::log_backtrace(&e),
+        }
+    })
+}
+
+/// Answer a single HTTP request with the matching file from the build
+/// directory, injecting the live reload script into HTML pages.
+fn serve_file(request: Request, build_dir: &Path, script: &str) {
+    let path = match resolve_request_path(request.url(), build_dir) {
+        Some(path) => path,
+        None => {
+            let _ = request.respond(Response::from_string("Not Found").with_status_code(404));
+            return;
+        }
+    };
+
+    debug!("Serving {}", path.display());
+
+    let mut content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Unable to read {}: {}", path.display(), e);
+            let _ = request.respond(Response::from_string("Not Found").with_status_code(404));
+            return;
+        }
+    };
+
+    let content_type = content_type(&path);
+    if content_type.starts_with("text/html") {
+        content = inject_script(content, script);
+    }
+
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
+        .expect("content types are valid header values");
+    let _ = request.respond(Response::from_data(content).with_header(header));
+}
+
+/// Map a request URL onto a file inside the build directory, refusing to
+/// leave it.
+fn resolve_request_path(url: &str, build_dir: &Path) -> Option<PathBuf> {
+    let url = url.split(['?', '#']).next().unwrap_or("");
+    let url = percent_decode_str(url).decode_utf8().ok()?;
+
+    let mut path = build_dir.to_path_buf();
+    for component in Path::new(url.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if path.is_dir() {
+        path.push("index.html");
+    }
+
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn inject_script(content: Vec<u8>, script: &str) -> Vec<u8> {
+    let mut html = String::from_utf8_lossy(&content).into_owned();
+    match html.rfind("</body>") {
+        Some(pos) => html.insert_str(pos, script),
+        None => html.push_str(script),
+    }
+    html.into_bytes()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn resolve_request_paths() {
+        let build = TempDir::new().unwrap();
+        let root = build.path();
+        fs::create_dir_all(root.join("cat/book one")).unwrap();
+        fs::write(root.join("index.html"), "").unwrap();
+        fs::write(root.join("cat/book one/index.html"), "").unwrap();
+        fs::write(root.join("cat/book one/ch1.html"), "").unwrap();
+
+        let cases = [
+            ("/", Some("index.html")),
+            ("/index.html?reload=1", Some("index.html")),
+            ("/cat/book%20one/", Some("cat/book one/index.html")),
+            ("/cat/book%20one/ch1.html#setup", Some("cat/book one/ch1.html")),
+            ("/cat/./book%20one/ch1.html", Some("cat/book one/ch1.html")),
+            ("/cat/missing.html", None),
+            ("/../index.html", None),
+            ("/cat/../index.html", None),
+            ("/cat/%2e%2e/index.html", None),
+            ("/%ff", None),
+        ];
+
+        for (url, expected) in cases.iter() {
+            let expected = expected.map(|path| root.join(path));
+            assert_eq!(resolve_request_path(url, root), expected, "for {:?}", url);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::thread::sleep;
+use std::time::Duration;
+
+use clap::{App, ArgMatches, SubCommand};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+use jungle::book::*;
+use jungle::config::{load_config, CONFIG_FILE};
+use jungle::errors::*;
+use jungle::utils;
+
+use crate::cmd::{get_root_dir, open_index};
+
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("watch")
+        .about("Watches books' files and rebuilds them on changes")
+        // the {n} denotes a newline which will properly aligned in all help messages
+        .arg_from_usage(
+            "[dir] 'Root directory for the book{n}\
+             (Defaults to the Current Directory when omitted)'",
+        )
+        .arg_from_usage("-o, --open 'Opens the compiled book in a web browser'")
+}
+
+pub fn execute(args: &ArgMatches) -> Result<()> {
+    let root = get_root_dir(args);
+    trace!("root dir: {:?}", root);
+
+    let config = load_config(&root)?;
+    build(root.as_path(), &config)?;
+
+    if args.is_present("open") {
+        open_index(&root, &config);
+    }
+
+    trigger_on_change(root.as_path(), |paths, root| {
+        info!("Files changed: {:?}", paths);
+        info!("Building books...");
+
+        let result = load_config(root).and_then(|config| build(root, &config));
+        if let Err(e) = result {
+            utils::log_backtrace(&e);
+        }
+    })
+}
+
+/// Calls the closure with the changed paths whenever something the build
+/// depends on is modified. Events arriving in quick succession are handled
+/// as a single change.
+pub fn trigger_on_change<F>(root: &Path, closure: F) -> Result<()>
+    where
+        F: Fn(Vec<PathBuf>, &Path),
+{
+    let (tx, rx) = channel();
+
+    let mut watcher = notify::watcher(tx, Duration::from_secs(1))
+        .chain_err(|| "Unable to create the file watcher")?;
+
+    let config = load_config(root)?;
+    // Every language's books of a multilingual library
+    let src_dir = get_src_dir(root, &config);
+    watcher.watch(&src_dir, RecursiveMode::Recursive)
+        .chain_err(|| format!("Unable to watch {}", src_dir.display()))?;
+
+    // The config file is optional, so it's fine if it can't be watched
+    let _ = watcher.watch(root.join(CONFIG_FILE), RecursiveMode::NonRecursive);
+
+    info!("Listening for changes...");
+
+    loop {
+        let first_event = rx.recv().chain_err(|| "The file watcher stopped")?;
+        sleep(Duration::from_millis(50));
+        let other_events = rx.try_iter();
+
+        let paths: Vec<PathBuf> = std::iter::once(first_event)
+            .chain(other_events)
+            .filter_map(|event| {
+                debug!("Received filesystem event: {:?}", event);
+
+                match event {
+                    DebouncedEvent::Create(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Rename(_, path) => Some(path),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        if !paths.is_empty() {
+            closure(paths, root);
+        }
+    }
+}
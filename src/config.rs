@@ -0,0 +1,475 @@
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use toml::value::{Table, Value};
+
+use crate::errors::*;
+
+/// The name of the library configuration file, relative to the root directory.
+pub const CONFIG_FILE: &str = "jungle.toml";
+
+/// The name of a book's own configuration file, next to its `README.md`.
+pub const BOOK_CONFIG_FILE: &str = "book.toml";
+
+/// The built-in preprocessors, which run first unless the config's
+/// `build.use-default-preprocessors` is turned off.
+pub const DEFAULT_PREPROCESSORS: &[&str] = &["include", "links"];
+
+/// Load the configuration of the library rooted at `root_dir`, falling back
+/// to the defaults when there is no `jungle.toml`.
+pub fn load_config<P: AsRef<Path>>(root_dir: P) -> Result<Config> {
+    let config_file = root_dir.as_ref().join(CONFIG_FILE);
+
+    if config_file.exists() {
+        debug!("Loading config from {}", config_file.display());
+        Config::from_disk(&config_file)
+    } else {
+        debug!("No {} found, using the default config", CONFIG_FILE);
+        Ok(Config::default())
+    }
+}
+
+/// The overall configuration object for a library.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Metadata about the library.
+    pub library: LibraryConfig,
+    /// Information about the build environment.
+    pub build: BuildConfig,
+    /// The Markdown extensions every page is rendered with.
+    pub markdown: MarkdownConfig,
+    /// Every other table and key, available to renderers through `get()`.
+    #[serde(flatten)]
+    rest: Table,
+}
+
+impl Config {
+    /// Load the configuration file from disk.
+    pub fn from_disk<P: AsRef<Path>>(config_file: P) -> Result<Config> {
+        let config_file = config_file.as_ref();
+        let content = read_to_string(config_file)
+            .chain_err(|| format!("Unable to read {}", config_file.display()))?;
+
+        Config::from_str(&content)
+            .chain_err(|| format!("Invalid configuration file {}", config_file.display()))
+    }
+
+    /// Parse the configuration from its TOML representation.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(src: &str) -> Result<Config> {
+        let mut config: Config = toml::from_str(src).chain_err(|| "Malformed TOML")?;
+
+        // A multilingual library is in its default language until another
+        // one is built
+        if let Some(default) = config.library.languages.first() {
+            config.library.language = default.clone();
+        }
+
+        Ok(config)
+    }
+
+    /// Fetch an arbitrary item from the configuration, using a dotted key
+    /// such as `output.html.default-theme`.
+    ///
+    /// Only the tables `jungle` doesn't know about are searched, the known
+    /// ones are available as fields.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let mut parts = key.split('.');
+        let mut value = self.rest.get(parts.next()?)?;
+
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+
+        Some(value)
+    }
+
+    /// Fetch an item with `get()` and deserialize it into a `T`.
+    pub fn get_deserialized_opt<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.get(key) {
+            Some(value) => value.clone().try_into()
+                .map(Some)
+                .chain_err(|| format!("Invalid value for `{}`", key)),
+            None => Ok(None),
+        }
+    }
+
+    /// The names of the renderers listed with an `[output.<name>]` table, or
+    /// only `html` when there is none.
+    pub fn renderers(&self) -> Vec<String> {
+        match self.get("output").and_then(Value::as_table) {
+            Some(table) if !table.is_empty() => table.keys().cloned().collect(),
+            _ => vec![String::from("html")],
+        }
+    }
+
+    /// The names of the preprocessors to run, in order: the default ones not
+    /// listed in `build.preprocessors`, unless turned off, then the listed
+    /// ones.
+    pub fn preprocessors(&self) -> Vec<String> {
+        let listed = &self.build.preprocessors;
+        let defaults = DEFAULT_PREPROCESSORS.iter()
+            .filter(|_| self.build.use_default_preprocessors)
+            .filter(|name| !listed.iter().any(|p| p == *name))
+            .map(|name| name.to_string());
+
+        defaults.chain(listed.iter().cloned()).collect()
+    }
+
+    /// The language the others fall back to: the first of `languages`, or
+    /// the only one.
+    pub fn default_language(&self) -> &str {
+        self.library.languages.first().unwrap_or(&self.library.language)
+    }
+
+    /// The configuration building one of the library's `languages`.
+    pub fn for_language(&self, language: &str) -> Config {
+        let mut config = self.clone();
+        config.library.language = language.to_string();
+        config
+    }
+
+    /// Where the pages of a language go, relative to a renderer's output:
+    /// the default language at the top, the others in a directory named
+    /// after them.
+    pub fn language_dir(&self, language: &str) -> PathBuf {
+        if language == self.default_language() {
+            PathBuf::new()
+        } else {
+            PathBuf::from(language)
+        }
+    }
+
+    /// The configuration of the HTML renderer, from the `[output.html]` table.
+    pub fn html_config(&self) -> Result<HtmlConfig> {
+        self.get_deserialized_opt("output.html")
+            .map(Option::unwrap_or_default)
+    }
+}
+
+/// Configuration options which are specific to the library and required for
+/// its rendering.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LibraryConfig {
+    /// The library's title.
+    pub title: Option<String>,
+    /// A short description of the library.
+    pub description: Option<String>,
+    /// The main language of the library, or the one being built when it has
+    /// several `languages`.
+    pub language: String,
+    /// The languages of a multilingual library, the first one being the
+    /// default. Each has its own tree of books in the subdirectory of `src`
+    /// named after it, e.g. `src/zh` and `src/en`.
+    pub languages: Vec<String>,
+    /// Where the books' sources are, relative to the root directory.
+    pub src: PathBuf,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> LibraryConfig {
+        LibraryConfig {
+            title: None,
+            description: None,
+            language: String::from("en"),
+            languages: Vec::new(),
+            src: PathBuf::from("src"),
+        }
+    }
+}
+
+/// Configuration for the build procedure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BuildConfig {
+    /// Where to put the rendered library, relative to the root directory.
+    pub build_dir: PathBuf,
+    /// More preprocessors to run on every book, in order, after the default
+    /// ones. Besides the built-in ones, each can be a command set in a
+    /// `[preprocessor.<name>]` table.
+    pub preprocessors: Vec<String>,
+    /// Whether to run the built-in `include` and `links` preprocessors
+    /// before those of `preprocessors`, when they aren't listed there.
+    pub use_default_preprocessors: bool,
+    /// Whether to build the books and chapters marked as drafts, which are
+    /// left out otherwise.
+    pub drafts: bool,
+}
+
+impl Default for BuildConfig {
+    fn default() -> BuildConfig {
+        BuildConfig {
+            build_dir: PathBuf::from("build"),
+            preprocessors: Vec::new(),
+            use_default_preprocessors: true,
+            drafts: false,
+        }
+    }
+}
+
+/// Which Markdown extensions are enabled, from the `[markdown]` table of
+/// `jungle.toml` or of a book's `book.toml`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MarkdownConfig {
+    /// GitHub-style tables.
+    pub tables: bool,
+    /// Footnotes, as in `text[^1]` with a `[^1]: note` definition.
+    pub footnotes: bool,
+    /// `~~strikethrough~~` text.
+    pub strikethrough: bool,
+    /// Task lists, as in `- [x] done`.
+    pub tasklists: bool,
+    /// Curly quotes, dashes from `--` and `---`, and ellipses from `...`.
+    pub smart_punctuation: bool,
+    /// `$inline$` and `$$display$$` LaTeX formulas, rendered in the browser.
+    pub math: bool,
+}
+
+impl MarkdownConfig {
+    /// Apply the keys of a book's `[markdown]` table on top of these options.
+    pub fn with_overrides(&self, overrides: &Table) -> Result<MarkdownConfig> {
+        if overrides.is_empty() {
+            return Ok(self.clone());
+        }
+
+        let mut table = Value::try_from(self)
+            .chain_err(|| "Unable to serialize the Markdown options")?;
+        if let Value::Table(ref mut table) = table {
+            table.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+
+        table.try_into().chain_err(|| "Invalid `[markdown]` table")
+    }
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> MarkdownConfig {
+        MarkdownConfig {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: false,
+            math: false,
+        }
+    }
+}
+
+/// The configuration of a single book, from its `book.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookConfig {
+    /// The book's metadata, from the `[book]` table.
+    pub book: BookMetadata,
+    /// Markdown extensions turned on or off for this book only, overriding
+    /// the library's `[markdown]` table.
+    pub markdown: Table,
+    /// Whether the whole book is a draft.
+    pub draft: bool,
+    /// The chapters that are drafts, relative to the book's directory, e.g.
+    /// `["part1/intro.md"]`.
+    pub drafts: Vec<PathBuf>,
+    /// Every other table and key.
+    #[serde(flatten)]
+    rest: Table,
+}
+
+impl BookConfig {
+    /// Load a book's configuration file from disk.
+    pub fn from_disk<P: AsRef<Path>>(config_file: P) -> Result<BookConfig> {
+        let config_file = config_file.as_ref();
+        let content = read_to_string(config_file)
+            .chain_err(|| format!("Unable to read {}", config_file.display()))?;
+
+        toml::from_str(&content)
+            .chain_err(|| "Malformed TOML")
+            .chain_err(|| format!("Invalid configuration file {}", config_file.display()))
+    }
+}
+
+/// The metadata of a book, from the `[book]` table of its `book.toml`:
+///
+/// ```toml
+/// [book]
+/// title = "Getting started"
+/// authors = ["Jane Doe"]
+/// cover = "images/cover.png"
+/// order = 1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookMetadata {
+    /// The title shown instead of the one from the `README.md`, unless the
+    /// library's `SUMMARY.md` gives another one.
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    /// A short description, shown in the library's index.
+    pub description: Option<String>,
+    /// An image showing the book, relative to its directory.
+    pub cover: Option<PathBuf>,
+    /// The language the book is written in, when it's not the library's.
+    pub language: Option<String>,
+    pub tags: Vec<String>,
+    /// Where the book goes among the others of its category when the
+    /// library has no `SUMMARY.md`, the lowest first.
+    pub order: Option<i64>,
+}
+
+/// Configuration for the HTML renderer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HtmlConfig {
+    /// The theme the pages are shown with, used as the `<body>` class.
+    pub default_theme: String,
+    /// The highlighter's theme for code blocks, e.g. `InspiredGitHub` or
+    /// `base16-ocean.dark`.
+    pub highlight_theme: String,
+    /// The full-text search across the library.
+    pub search: Search,
+}
+
+impl Default for HtmlConfig {
+    fn default() -> HtmlConfig {
+        HtmlConfig {
+            default_theme: String::from("light"),
+            highlight_theme: String::from("InspiredGitHub"),
+            search: Search::default(),
+        }
+    }
+}
+
+/// Configuration of the search, from the `[output.html.search]` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Search {
+    /// Whether to generate the search index and show the search box.
+    pub enable: bool,
+    /// The maximum number of results shown.
+    pub limit_results: u32,
+}
+
+impl Default for Search {
+    fn default() -> Search {
+        Search {
+            enable: true,
+            limit_results: 30,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPLEX_CONFIG: &str = r#"
+        [library]
+        title = "Some Library"
+        description = "A library of books"
+        src = "books"
+
+        [build]
+        build-dir = "public"
+
+        [output.html]
+        default-theme = "dark"
+
+        [output.html.search]
+        limit-results = 10
+
+        [output.epub]
+        command = "jungle-epub"
+        "#;
+
+    #[test]
+    fn parse_a_complex_config() {
+        let config = Config::from_str(COMPLEX_CONFIG).unwrap();
+
+        assert_eq!(config.library.title.as_deref(), Some("Some Library"));
+        assert_eq!(config.library.description.as_deref(), Some("A library of books"));
+        assert_eq!(config.library.language, "en");
+        assert_eq!(config.library.src, PathBuf::from("books"));
+        assert_eq!(config.build.build_dir, PathBuf::from("public"));
+        assert_eq!(config.markdown, MarkdownConfig::default());
+        assert_eq!(config.renderers(), vec!["epub", "html"]);
+    }
+
+    #[test]
+    fn fetch_arbitrary_values() {
+        let config = Config::from_str(COMPLEX_CONFIG).unwrap();
+
+        assert_eq!(config.get("output.html.default-theme"), Some(&Value::from("dark")));
+        assert_eq!(config.get("output.html.missing"), None);
+        assert_eq!(config.get("output.epub.command.more"), None);
+        assert_eq!(config.get("library"), None);
+        assert_eq!(config.get_deserialized_opt::<String>("output.epub.command").unwrap(),
+                   Some(String::from("jungle-epub")));
+        assert!(config.get_deserialized_opt::<u32>("output.epub.command").is_err());
+
+        let html = config.html_config().unwrap();
+        assert_eq!(html.default_theme, "dark");
+        assert_eq!(html.search, Search { enable: true, limit_results: 10 });
+    }
+
+    #[test]
+    fn use_the_defaults() {
+        let config = Config::from_str("").unwrap();
+
+        assert_eq!(config, Config::default());
+        assert_eq!(config.renderers(), vec!["html"]);
+        assert_eq!(config.html_config().unwrap(), HtmlConfig::default());
+    }
+
+    #[test]
+    fn reject_invalid_configs() {
+        let cases = [
+            "[library",
+            "[library]\ntitle = 1",
+            "[build]\nbuild-dir = []",
+        ];
+
+        for src in cases.iter() {
+            assert!(Config::from_str(src).is_err(), "in {:?}", src);
+        }
+    }
+
+    #[test]
+    fn order_the_preprocessors() {
+        let cases: [(&str, &[&str]); 5] = [
+            ("", &["include", "links"]),
+            ("[build]\npreprocessors = [\"glossary\"]", &["include", "links", "glossary"]),
+            ("[build]\npreprocessors = [\"glossary\", \"links\"]", &["include", "glossary", "links"]),
+            ("[build]\nuse-default-preprocessors = false", &[]),
+            ("[build]\nuse-default-preprocessors = false\npreprocessors = [\"links\", \"glossary\"]",
+             &["links", "glossary"]),
+        ];
+
+        for (src, expected) in cases.iter() {
+            assert_eq!(&Config::from_str(src).unwrap().preprocessors(), expected, "in {:?}", src);
+        }
+    }
+
+    #[test]
+    fn override_the_markdown_options() {
+        let library = Config::from_str("[markdown]\nmath = true\ntables = false").unwrap().markdown;
+        assert!(library.math && !library.tables);
+
+        let cases = [
+            ("", library.clone()),
+            ("[markdown]\nmath = false", MarkdownConfig { math: false, ..library.clone() }),
+            ("[markdown]\ntables = true\nsmart-punctuation = true",
+             MarkdownConfig { tables: true, smart_punctuation: true, ..library.clone() }),
+        ];
+
+        for (src, expected) in cases.iter() {
+            let book: BookConfig = toml::from_str(src).unwrap();
+            assert_eq!(&library.with_overrides(&book.markdown).unwrap(), expected, "in {:?}", src);
+        }
+
+        let book: BookConfig = toml::from_str("[markdown]\nmath = \"yes\"").unwrap();
+        assert!(library.with_overrides(&book.markdown).is_err());
+    }
+}
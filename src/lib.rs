@@ -12,6 +12,8 @@ extern crate serde_json;
 pub const VERSION: &str = concat!("v", crate_version!());
 
 pub mod book;
+pub mod config;
+pub mod preprocess;
 pub mod render;
 pub mod theme;
 pub mod utils;
@@ -33,7 +35,7 @@ pub mod errors {
             /// A subprocess exited with an unsuccessful return code.
             Subprocess(message: String, output: std::process::Output) {
                 description("A subprocess failed")
-                display("{}: {}", message, String::from_utf8_lossy(&output.stdout))
+                display("{}: {}", message, subprocess_output(output))
             }
 
             /// An error was encountered while parsing the `SUMMARY.md` file.
@@ -52,6 +54,17 @@ pub mod errors {
         }
     }
 
+    /// What a failed subprocess had to say: its stderr, or else its stdout.
+    fn subprocess_output(output: &std::process::Output) -> String {
+        let text = if output.stderr.iter().all(u8::is_ascii_whitespace) {
+            &output.stdout
+        } else {
+            &output.stderr
+        };
+
+        String::from_utf8_lossy(text).trim().to_string()
+    }
+
     // Box to halve the size of Error
     impl From<handlebars::TemplateError> for Error {
         fn from(e: handlebars::TemplateError) -> Error {
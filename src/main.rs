@@ -3,14 +3,30 @@ extern crate clap;
 #[macro_use]
 extern crate log;
 
+use std::env;
+
 use clap::{App, AppSettings};
+use log::LevelFilter;
 
 use jungle::utils;
 
 mod cmd;
 
+/// Show warnings, like skipped drafts and unlisted chapters, unless `RUST_LOG`
+/// says otherwise.
+fn init_logger() {
+    let mut builder = pretty_env_logger::formatted_builder();
+
+    match env::var("RUST_LOG") {
+        Ok(filters) => builder.parse_filters(&filters),
+        Err(_) => builder.filter_level(LevelFilter::Warn),
+    };
+
+    builder.init();
+}
+
 fn main() {
-    pretty_env_logger::init();
+    init_logger();
 
     let app = App::new(crate_name!())
         .about(crate_description!())
@@ -21,11 +37,15 @@ fn main() {
         .setting(AppSettings::ColoredHelp)
         .after_help("For more information about a specific command, try `mdbook <command> --help`")
         .subcommand(cmd::init::make_subcommand())
-        .subcommand(cmd::build::make_subcommand());
+        .subcommand(cmd::build::make_subcommand())
+        .subcommand(cmd::serve::make_subcommand())
+        .subcommand(cmd::watch::make_subcommand());
 
     let matches = match app.get_matches().subcommand() {
         ("init", Some(sub_matches)) => cmd::init::execute(sub_matches),
         ("build", Some(sub_matches)) => cmd::build::execute(sub_matches),
+        ("serve", Some(sub_matches)) => cmd::serve::execute(sub_matches),
+        ("watch", Some(sub_matches)) => cmd::watch::execute(sub_matches),
         (_, _) => unreachable!(),
     };
 
@@ -0,0 +1,59 @@
+use std::process::Command;
+
+use crate::book::Book;
+use crate::errors::*;
+use crate::preprocess::{Preprocessor, PreprocessorContext};
+use crate::utils::{run_command, split_command};
+
+/// A preprocessor which runs an external command, exchanging JSON with it.
+///
+/// The command gets a `[context, book]` array on stdin and must print the
+/// updated book on stdout. It runs in the library's root directory, after the
+/// built-in `include` and `links` preprocessors, e.g.
+///
+/// ```toml
+/// [build]
+/// preprocessors = ["glossary"]
+///
+/// [preprocessor.glossary]
+/// command = "python3 scripts/glossary.py"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdPreprocessor {
+    name: String,
+    cmd: String,
+}
+
+impl CmdPreprocessor {
+    /// Create a new `CmdPreprocessor` which will invoke the provided `cmd`
+    /// string.
+    pub fn new(name: String, cmd: String) -> CmdPreprocessor {
+        CmdPreprocessor { name, cmd }
+    }
+
+    fn command(&self) -> Result<Command> {
+        let (program, args) = split_command(&self.cmd)
+            .chain_err(|| format!("Invalid command for the {} preprocessor", self.name))?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        Ok(cmd)
+    }
+}
+
+impl Preprocessor for CmdPreprocessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, book: Book) -> Result<Book> {
+        debug!("Invoking the \"{}\" preprocessor on {}", self.name, book.name);
+
+        let mut cmd = self.command()?;
+        cmd.current_dir(&ctx.root);
+        let output = run_command(&mut cmd, serde_json::to_vec(&(ctx, &book))?, &self.name, "preprocessor")?;
+
+        serde_json::from_slice(&output.stdout)
+            .chain_err(|| format!("The \"{}\" preprocessor didn't print a valid book", self.name))
+    }
+}
@@ -0,0 +1,331 @@
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use crate::book::{Book, BookItem, SourceLine};
+use crate::errors::*;
+use crate::preprocess::{Preprocessor, PreprocessorContext};
+
+const DIRECTIVE: &str = "{{#include";
+
+/// A preprocessor replacing `{{#include path}}` directives with the content
+/// of the file, relative to the including chapter:
+///
+/// - `{{#include example.rs}}` includes the whole file,
+/// - `{{#include example.rs:3:10}}` its lines 3 to 10, and `:3`, `:3:` or
+///   `::10` only line 3, from line 3 or up to line 10,
+/// - `{{#include example.rs:setup}}` the lines between the comments holding
+///   `ANCHOR: setup` and `ANCHOR_END: setup`.
+///
+/// Included files may include others in turn. A directive is kept as is
+/// when escaped with a backslash, as in `\{{#include example.rs}}`.
+#[derive(Default)]
+pub struct IncludePreprocessor;
+
+impl IncludePreprocessor {
+    pub const NAME: &'static str = "include";
+
+    pub fn new() -> Self {
+        IncludePreprocessor
+    }
+}
+
+impl Preprocessor for IncludePreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, _ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let readme = book.root.join("README.md");
+        let expansion = include_files(&book.readme, &readme, book.front_matter_lines)?;
+        book.readme = expansion.text;
+        book.source_lines = expansion.lines;
+
+        let mut result = Ok(());
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ch) = item {
+                if result.is_ok() {
+                    match include_files(&ch.content, &ch.path, ch.front_matter_lines) {
+                        Ok(expansion) => {
+                            ch.content = expansion.text;
+                            ch.source_lines = expansion.lines;
+                        }
+                        Err(e) => result = Err(e),
+                    }
+                }
+            }
+        });
+        result?;
+
+        Ok(book)
+    }
+}
+
+/// Markdown with its files included, along with where each of its lines
+/// was written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    pub lines: Vec<SourceLine>,
+}
+
+impl Expansion {
+    /// Append `content[start..end]`, the lines of `content` being written at
+    /// `lines`.
+    fn push_source(&mut self, content: &str, start: usize, end: usize, lines: &[SourceLine]) {
+        let first = content[..start].matches('\n').count();
+        let breaks = content[start..end].matches('\n').count();
+        self.lines.extend(lines.iter().skip(first + 1).take(breaks).cloned());
+        self.text.push_str(&content[start..end]);
+    }
+
+    /// Append another expansion, whose first line continues the current one.
+    fn append(&mut self, other: Expansion) {
+        self.text.push_str(&other.text);
+        self.lines.extend(other.lines.into_iter().skip(1));
+    }
+}
+
+/// Expand the include directives of `content`, the Markdown of the file at
+/// `path` after its first `skipped_lines`, e.g. those of its front matter.
+pub fn include_files(content: &str, path: &Path, skipped_lines: usize) -> Result<Expansion> {
+    let lines: Vec<SourceLine> = (0..=content.matches('\n').count())
+        .map(|i| SourceLine::new(path, skipped_lines + i + 1))
+        .collect();
+    let mut stack = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+    expand(content, &lines, path, &mut stack)
+}
+
+/// The part of a file to include.
+#[derive(Debug, Clone, PartialEq)]
+enum Range {
+    All,
+    /// From the first line to the last one, both included and counted from 1.
+    Lines(Option<usize>, Option<usize>),
+    Anchor(String),
+}
+
+/// Expand the include directives of `content`, from the file at `path`,
+/// each of its lines being written at `lines`.
+fn expand(content: &str, lines: &[SourceLine], path: &Path, stack: &mut Vec<PathBuf>)
+          -> Result<Expansion> {
+    let mut result = Expansion {
+        text: String::with_capacity(content.len()),
+        lines: lines.iter().take(1).cloned().collect(),
+    };
+    let mut pos = 0;
+
+    while let Some(start) = content[pos..].find(DIRECTIVE).map(|start| pos + start) {
+        let end = match content[start..].find("}}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+
+        if content[..start].ends_with('\\') {
+            result.push_source(content, pos, start - 1, lines);
+            result.push_source(content, start, end, lines);
+            pos = end;
+            continue;
+        }
+
+        let location = &lines[content[..start].matches('\n').count()];
+        let args = content[start + DIRECTIVE.len()..end - 2].trim();
+        let included = include(args, path, stack)
+            .chain_err(|| format!("Unable to include `{}` at {}", args, location))?;
+
+        result.push_source(content, pos, start, lines);
+        result.append(included);
+        pos = end;
+    }
+
+    result.push_source(content, pos, content.len(), lines);
+    Ok(result)
+}
+
+fn include(args: &str, path: &Path, stack: &mut Vec<PathBuf>) -> Result<Expansion> {
+    let (file, range) = parse_args(args)?;
+
+    let target = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+    let canonical = target.canonicalize()
+        .chain_err(|| format!("{} doesn't exist", target.display()))?;
+
+    if stack.contains(&canonical) {
+        bail!("{} includes itself", target.display());
+    }
+
+    let text = read_to_string(&target)
+        .chain_err(|| format!("Unable to read {}", target.display()))?;
+    let (selected, numbers) = select(&text, &range)?;
+    let lines: Vec<SourceLine> = numbers.into_iter()
+        .map(|line| SourceLine::new(&target, line))
+        .collect();
+
+    stack.push(canonical);
+    let expanded = expand(&selected, &lines, &target, stack);
+    stack.pop();
+
+    expanded
+}
+
+fn parse_args(args: &str) -> Result<(&str, Range)> {
+    let (file, spec) = match args.find(':') {
+        Some(pos) => (&args[..pos], Some(&args[pos + 1..])),
+        None => (args, None),
+    };
+
+    if file.is_empty() {
+        bail!("No file to include");
+    }
+
+    let range = match spec {
+        None => Range::All,
+        Some(spec) if spec.contains(':') => {
+            let (from, to) = spec.split_at(spec.find(':').expect("checked above"));
+            Range::Lines(parse_line(from)?, parse_line(&to[1..])?)
+        }
+        Some(spec) if spec.chars().all(|c| c.is_ascii_digit()) => {
+            let line = parse_line(spec)?;
+            Range::Lines(line, line)
+        }
+        Some(name) => Range::Anchor(name.to_string()),
+    };
+
+    Ok((file, range))
+}
+
+fn parse_line(number: &str) -> Result<Option<usize>> {
+    if number.is_empty() {
+        return Ok(None);
+    }
+
+    match number.parse() {
+        Ok(0) | Err(_) => bail!("Invalid line number `{}`, lines are counted from 1", number),
+        Ok(line) => Ok(Some(line)),
+    }
+}
+
+/// The lines of `text` in `range`, joined, along with their numbers.
+fn select(text: &str, range: &Range) -> Result<(String, Vec<usize>)> {
+    let numbered = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let lines: Vec<(usize, &str)> = match *range {
+        Range::All => numbered.collect(),
+        Range::Lines(from, to) => {
+            let from = from.unwrap_or(1);
+            let count = to.map_or(usize::MAX, |to| (to + 1).saturating_sub(from));
+            numbered.skip(from - 1).take(count).collect()
+        }
+        Range::Anchor(ref name) => {
+            let mut lines = numbered
+                .skip_while(|(_, line)| anchor_name(line, "ANCHOR:") != Some(name));
+
+            if lines.next().is_none() {
+                bail!("There is no `ANCHOR: {}`", name);
+            }
+
+            lines.take_while(|(_, line)| anchor_name(line, "ANCHOR_END:") != Some(name))
+                .collect()
+        }
+    };
+
+    // The anchors only matter to the directives, e.g. when nested
+    let (numbers, lines): (Vec<usize>, Vec<&str>) = lines.into_iter()
+        .filter(|(_, line)| !line.contains("ANCHOR:") && !line.contains("ANCHOR_END:"))
+        .unzip();
+
+    Ok((lines.join("\n"), numbers))
+}
+
+/// The name of the anchor a line starts or ends, given the `marker`.
+fn anchor_name<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let pos = line.find(marker)?;
+    line[pos + marker.len()..].split_whitespace().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_include_args() {
+        let cases = [
+            ("example.rs", ("example.rs", Range::All)),
+            ("src/main.rs:3", ("src/main.rs", Range::Lines(Some(3), Some(3)))),
+            ("example.rs:3:10", ("example.rs", Range::Lines(Some(3), Some(10)))),
+            ("example.rs:3:", ("example.rs", Range::Lines(Some(3), None))),
+            ("example.rs::10", ("example.rs", Range::Lines(None, Some(10)))),
+            ("example.rs::", ("example.rs", Range::Lines(None, None))),
+            ("example.rs:setup", ("example.rs", Range::Anchor("setup".to_string()))),
+        ];
+
+        for (args, expected) in cases.iter() {
+            assert_eq!(&parse_args(args).unwrap(), expected, "in {:?}", args);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_include_args() {
+        let cases = ["", ":3", "example.rs:0", "example.rs:0:2", "example.rs:1:x", "example.rs:-1:"];
+
+        for args in cases.iter() {
+            assert!(parse_args(args).is_err(), "in {:?}", args);
+        }
+    }
+
+    #[test]
+    fn select_lines() {
+        let text = "one\n// ANCHOR: setup\ntwo\nthree\n// ANCHOR_END: setup\nfour\n";
+        let cases = [
+            (Range::All, "one\ntwo\nthree\nfour"),
+            (Range::Lines(Some(1), Some(1)), "one"),
+            (Range::Lines(Some(3), Some(4)), "two\nthree"),
+            (Range::Lines(Some(5), None), "four"),
+            (Range::Lines(None, Some(3)), "one\ntwo"),
+            (Range::Lines(Some(4), Some(2)), ""),
+            (Range::Lines(Some(9), None), ""),
+            (Range::Anchor("setup".to_string()), "two\nthree"),
+        ];
+
+        for (range, expected) in cases.iter() {
+            assert_eq!(select(text, range).unwrap().0, *expected, "with {:?}", range);
+        }
+    }
+
+    #[test]
+    fn select_nested_anchors() {
+        let text = "// ANCHOR: all\na\n// ANCHOR: part\nb\n// ANCHOR_END: part\nc\n// ANCHOR_END: all\n";
+
+        assert_eq!(select(text, &Range::Anchor("all".to_string())).unwrap(),
+                   (String::from("a\nb\nc"), vec![2, 4, 6]));
+        assert_eq!(select(text, &Range::Anchor("part".to_string())).unwrap(),
+                   (String::from("b"), vec![4]));
+        assert!(select(text, &Range::Anchor("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn keep_escaped_directives() {
+        let text = "a \\{{#include example.rs}} b {{#include";
+        assert_eq!(include_files(text, Path::new("chapter.md"), 0).unwrap().text,
+                   "a {{#include example.rs}} b {{#include");
+    }
+
+    #[test]
+    fn map_included_lines() {
+        let temp = TempDir::new().unwrap();
+        let chapter = temp.path().join("chapter.md");
+        let part = temp.path().join("part.md");
+        fs::write(&part, "// ANCHOR: part\nx\n// ANCHOR_END: part\ny\n").unwrap();
+
+        let text = "a\n{{#include part.md}} b\n\\{{#include part.md}}\n{{#include part.md:part}}";
+        let expansion = include_files(text, &chapter, 3).unwrap();
+
+        assert_eq!(expansion.text, "a\nx\ny b\n{{#include part.md}}\nx");
+        assert_eq!(expansion.lines, vec![
+            SourceLine::new(&chapter, 4),
+            SourceLine::new(&chapter, 5),
+            SourceLine::new(&part, 4),
+            SourceLine::new(&chapter, 6),
+            SourceLine::new(&chapter, 7),
+        ]);
+    }
+}
@@ -0,0 +1,544 @@
+use std::fs::read_to_string;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, LinkType, Parser, Tag};
+
+use crate::book::{get_books_dir, load_book_config, Book, BookItem, FrontMatter, SourceLine};
+use crate::config::MarkdownConfig;
+use crate::errors::*;
+use crate::preprocess::{Preprocessor, PreprocessorContext};
+use crate::render::render_markdown_page;
+use crate::utils::{normalize_id, normalize_path, path_to_url, relative_path};
+
+/// A preprocessor pointing links to Markdown files at the HTML pages they
+/// are rendered to, and resolving references to other books.
+///
+/// A reference is a link such as `[Setup](book:cat/book1:ch1#setup)`,
+/// naming a book by its path in the library or, when that's unambiguous, by
+/// its directory name, then optionally one of its chapters by path without
+/// `.md`, e.g. `ch1` or `part1/intro`, and a heading by `id` or text. It
+/// becomes a relative link to the page, and fails the build when there's
+/// no such book, chapter or heading.
+#[derive(Default)]
+pub struct LinkPreprocessor;
+
+impl LinkPreprocessor {
+    pub const NAME: &'static str = "links";
+
+    pub fn new() -> Self {
+        LinkPreprocessor
+    }
+}
+
+impl Preprocessor for LinkPreprocessor {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn run(&self, ctx: &PreprocessorContext, mut book: Book) -> Result<Book> {
+        let src_dir = get_books_dir(&ctx.root, &ctx.config);
+
+        let readme = book.root.join("README.md");
+        book.readme = rewrite_file_links(ctx, &book.readme, &readme, book.front_matter_lines,
+                                         &book.source_lines, &src_dir)?;
+
+        let mut result = Ok(());
+        book.for_each_mut(|item| {
+            if let BookItem::Chapter(ch) = item {
+                if result.is_ok() {
+                    match rewrite_file_links(ctx, &ch.content, &ch.path, ch.front_matter_lines,
+                                             &ch.source_lines, &src_dir) {
+                        Ok(content) => ch.content = content,
+                        Err(e) => result = Err(e),
+                    }
+                }
+            }
+        });
+        result?;
+
+        Ok(book)
+    }
+}
+
+/// Rewrite the links of `content`, the Markdown of the file at `path` after
+/// its first `skipped_lines`, e.g. those of its front matter, or else
+/// written at `source_lines` when other files are included in it.
+fn rewrite_file_links(ctx: &PreprocessorContext,
+                      content: &str,
+                      path: &Path,
+                      skipped_lines: usize,
+                      source_lines: &[SourceLine],
+                      src_dir: &Path) -> Result<String> {
+    rewrite_links(content, |dest, offset| {
+        if dest.starts_with(BOOK_SCHEME) {
+            let index = content[..offset].matches('\n').count();
+            let location = source_lines.get(index).cloned()
+                .unwrap_or_else(|| SourceLine::new(path, skipped_lines + index + 1));
+            resolve_reference(ctx, dest, path, src_dir)
+                .map(Some)
+                .chain_err(|| format!("Unable to resolve `{}` at {}", dest, location))
+        } else {
+            Ok(html_link(dest, path, src_dir))
+        }
+    })
+}
+
+/// The scheme of references to other books.
+const BOOK_SCHEME: &str = "book:";
+
+/// Turn a `book:<book>[:<chapter>][#heading]` reference into a link
+/// relative to the Markdown file at `from`.
+fn resolve_reference(ctx: &PreprocessorContext, dest: &str, from: &Path, src_dir: &Path)
+                     -> Result<String> {
+    let reference = &dest[BOOK_SCHEME.len()..];
+    let (target, heading) = match reference.find('#') {
+        Some(pos) => (&reference[..pos], Some(&reference[pos + 1..])),
+        None => (reference, None),
+    };
+    let (name, chapter) = match target.find(':') {
+        Some(pos) => (&target[..pos], Some(&target[pos + 1..])),
+        None => (target, None),
+    };
+
+    let book = find_book(ctx, name.trim_matches('/'), src_dir)?;
+
+    let (source, page) = match chapter {
+        Some(chapter) => {
+            let stem = chapter.strip_suffix(".md").unwrap_or(chapter);
+            let source = book.join(format!("{}.md", stem));
+            if stem.is_empty() || stem.eq_ignore_ascii_case("README") || !source.is_file() {
+                bail!("The book {} has no chapter `{}`", book.display(), chapter);
+            }
+
+            // A section's page is the index of its directory
+            let page = if source.file_name().is_some_and(|name| name == "README.md") {
+                source.with_file_name("index.html")
+            } else {
+                source.with_extension("html")
+            };
+            (source, page)
+        }
+        None => (book.join("README.md"), book.join("index.html")),
+    };
+
+    let fragment = match heading {
+        Some(heading) => {
+            // The target's headings get their ids from its book's options
+            let markdown = ctx.config.markdown.with_overrides(&load_book_config(&book)?.markdown)
+                .chain_err(|| format!("Invalid Markdown options for the book {}", book.display()))?;
+            format!("#{}", find_heading(&source, heading, &markdown)?)
+        }
+        None => String::new(),
+    };
+
+    let from_dir = from.parent().unwrap_or_else(|| Path::new(""));
+    Ok(format!("{}{}", path_to_url(relative_path(from_dir, &page)), fragment))
+}
+
+/// The directory of the book of the summary named `name`.
+fn find_book(ctx: &PreprocessorContext, name: &str, src_dir: &Path) -> Result<PathBuf> {
+    let books: Vec<&Path> = ctx.summary.iter()
+        .filter(|link| link.is_book)
+        .map(|link| link.path.as_path())
+        .collect();
+
+    let by_path = src_dir.join(name);
+    if let Some(book) = books.iter().find(|&&book| book == by_path) {
+        return Ok(book.to_path_buf());
+    }
+
+    let by_id: Vec<&&Path> = books.iter()
+        .filter(|book| book.file_name().is_some_and(|n| n == name))
+        .collect();
+
+    match by_id.as_slice() {
+        [book] => Ok(book.to_path_buf()),
+        [] => bail!("There is no book `{}` in the summary", name),
+        _ => {
+            let paths = by_id.iter()
+                .map(|book| book.strip_prefix(src_dir).unwrap_or(book).display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            bail!("`{}` may be any of {}, use its path instead", name, paths)
+        }
+    }
+}
+
+/// The `id` of a heading of the Markdown file at `path`, given either as is
+/// or by its text.
+fn find_heading(path: &Path, heading: &str, markdown: &MarkdownConfig) -> Result<String> {
+    let text = read_to_string(path)
+        .chain_err(|| format!("Unable to read {}", path.display()))?;
+    let (_, content) = FrontMatter::split(&text)
+        .chain_err(|| format!("Invalid front matter in {}", path.display()))?;
+    let ids: Vec<String> = render_markdown_page(content, markdown)
+        .outline
+        .into_iter()
+        .map(|heading| heading.id)
+        .collect();
+
+    let normalized = normalize_id(heading);
+    [heading, normalized.as_str()].iter()
+        .find(|id| ids.iter().any(|other| other == *id))
+        .map(|id| id.to_string())
+        .ok_or_else(|| format!("{} has no heading `{}`", path.display(), heading).into())
+}
+
+/// Point a link of the Markdown file at `from` to the page a Markdown file
+/// of the library is rendered to, keeping its query and fragment, e.g.
+/// `../intro.md#setup` becomes `../intro.html#setup`. A book's `README.md`
+/// becomes its `index.html`.
+///
+/// Absolute URLs and paths, links to other kinds of files and links leading
+/// out of the library are left alone.
+fn html_link(dest: &str, from: &Path, src_dir: &Path) -> Option<String> {
+    let end = dest.find(['?', '#']).unwrap_or(dest.len());
+    let (path, suffix) = dest.split_at(end);
+
+    if path.is_empty() || path.starts_with('/') || path.starts_with('\\') || has_scheme(path) {
+        return None;
+    }
+
+    let stem = path.strip_suffix(".md")?;
+
+    let target = normalize_path(from.parent()?.join(path));
+    if !target.starts_with(normalize_path(src_dir)) {
+        return None;
+    }
+
+    let is_readme = Path::new(path).file_name()?.eq_ignore_ascii_case("README.md");
+    let page = if is_readme {
+        format!("{}index.html", &path[..path.len() - "README.md".len()])
+    } else {
+        format!("{}.html", stem)
+    };
+
+    Some(format!("{}{}", page, suffix))
+}
+
+/// Whether a link starts with a URL scheme, such as `https:` or `mailto:`.
+fn has_scheme(dest: &str) -> bool {
+    match dest.find(':') {
+        Some(pos) => {
+            let scheme = &dest[..pos];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Rewrite the destinations of the inline links and link reference
+/// definitions in some Markdown, leaving the rest of the text untouched.
+///
+/// `rewrite` gets every destination, with the offset of its link, and
+/// returns its replacement, if any.
+pub fn rewrite_links<F>(content: &str, mut rewrite: F) -> Result<String>
+    where
+        F: FnMut(&str, usize) -> Result<Option<String>>,
+{
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut code_blocks = Vec::new();
+
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link(LinkType::Inline, dest, _)) => {
+                if let Some(new_dest) = rewrite(&dest, range.start)? {
+                    // The parser's range only covers the opening bracket
+                    if let Some(pos) = find_inline_destination(&content[range.start..], &dest) {
+                        let start = range.start + pos;
+                        edits.push((start..start + dest.len(), new_dest));
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => code_blocks.push(range),
+            _ => {}
+        }
+    }
+
+    // The destinations of reference links are in their definitions, which
+    // the parser doesn't report
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let in_code = code_blocks.iter().any(|block| block.contains(&offset));
+        if let (false, Some((pos, dest))) = (in_code, reference_definition(line)) {
+            if let Some(new_dest) = rewrite(dest, offset + pos)? {
+                let start = offset + pos;
+                edits.push((start..start + dest.len(), new_dest));
+            }
+        }
+        offset += line.len();
+    }
+
+    edits.sort_by_key(|(range, _)| range.start);
+
+    let mut rewritten = String::with_capacity(content.len());
+    let mut last = 0;
+    for (range, new_dest) in edits {
+        if range.start < last {
+            continue;
+        }
+        rewritten.push_str(&content[last..range.start]);
+        rewritten.push_str(&new_dest);
+        last = range.end;
+    }
+    rewritten.push_str(&content[last..]);
+
+    Ok(rewritten)
+}
+
+/// Find where the destination of an inline link starts in the source
+/// following its opening bracket, e.g. `[text](dest "title")`.
+fn find_inline_destination(link: &str, dest: &str) -> Option<usize> {
+    link.match_indices("](").find_map(|(pos, _)| {
+        let after = &link[pos + 2..];
+        let trimmed = after.trim_start().trim_start_matches('<');
+        if trimmed.starts_with(dest) {
+            Some(pos + 2 + (after.len() - trimmed.len()))
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse a link reference definition such as `[label]: dest "title"`,
+/// returning where its destination starts in the line.
+fn reference_definition(line: &str) -> Option<(usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 || !line[indent..].starts_with('[') {
+        return None;
+    }
+
+    let label_end = line[indent..].find("]:")? + indent;
+    if line[indent + 1..label_end].is_empty() || line[indent + 1..label_end].contains(']') {
+        return None;
+    }
+
+    let rest = &line[label_end + 2..];
+    let dest = rest.trim_start().trim_start_matches('<');
+    let start = line.len() - dest.len();
+    let dest = dest.split(|c: char| c.is_whitespace() || c == '>').next()?;
+
+    if dest.is_empty() {
+        None
+    } else {
+        Some((start, dest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book::{Link, Summary, SummaryItem};
+    use crate::config::Config;
+    use crate::preprocess::include::include_files;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A library with the books `cat/book1`, `cat/book2` and `other/book1`.
+    fn library() -> (TempDir, PreprocessorContext) {
+        let temp = TempDir::new().unwrap();
+        let src_dir = temp.path().join("src");
+        let files = [
+            ("cat/book1/README.md", "# Book one\n\n## Setup\n"),
+            ("cat/book1/ch1.md", "# Chapter one\n\n## Install it\n"),
+            ("cat/book1/part1/README.md", "# Part one\n"),
+            ("cat/book2/README.md", "# Book two\n"),
+            ("cat/book2/setup.md", "a\nb\n[c](book:nowhere)\n"),
+            ("other/book1/README.md", "# Other book one\n"),
+        ];
+        for (path, text) in files.iter() {
+            let path = src_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        let mut summary = Summary::new();
+        for book in ["cat/book1", "cat/book2", "other/book1"].iter() {
+            let mut link = Link::new(*book, src_dir.join(book));
+            link.is_book = true;
+            summary.items.push(SummaryItem::Link(link));
+        }
+
+        let ctx = PreprocessorContext::new(temp.path(), Config::default(), summary);
+        (temp, ctx)
+    }
+
+    #[test]
+    fn find_books() {
+        let (temp, ctx) = library();
+        let src_dir = temp.path().join("src");
+
+        assert_eq!(find_book(&ctx, "cat/book1", &src_dir).unwrap(), src_dir.join("cat/book1"));
+        assert_eq!(find_book(&ctx, "book2", &src_dir).unwrap(), src_dir.join("cat/book2"));
+        assert!(find_book(&ctx, "nope", &src_dir).is_err());
+
+        let ambiguous = find_book(&ctx, "book1", &src_dir).unwrap_err().to_string();
+        let expected = format!("`book1` may be any of {}, {}, use its path instead",
+                               Path::new("cat/book1").display(), Path::new("other/book1").display());
+        assert_eq!(ambiguous, expected);
+    }
+
+    #[test]
+    fn resolve_references() {
+        let (temp, ctx) = library();
+        let src_dir = temp.path().join("src");
+        let from = src_dir.join("cat/book2/intro.md");
+        let cases = [
+            ("book:cat/book1", "../book1/index.html"),
+            ("book:/cat/book1/", "../book1/index.html"),
+            ("book:book2", "index.html"),
+            ("book:other/book1", "../../other/book1/index.html"),
+            ("book:cat/book1#setup", "../book1/index.html#setup"),
+            ("book:cat/book1:ch1", "../book1/ch1.html"),
+            ("book:cat/book1:ch1.md#install-it", "../book1/ch1.html#install-it"),
+            ("book:cat/book1:ch1#Install it", "../book1/ch1.html#install-it"),
+            ("book:cat/book1:part1/README", "../book1/part1/index.html"),
+        ];
+
+        for (dest, expected) in cases.iter() {
+            assert_eq!(resolve_reference(&ctx, dest, &from, &src_dir).unwrap(), *expected,
+                       "for {:?}", dest);
+        }
+    }
+
+    #[test]
+    fn reject_broken_references() {
+        let (temp, ctx) = library();
+        let src_dir = temp.path().join("src");
+        let from = src_dir.join("cat/book2/intro.md");
+        let cases = [
+            "book:nope",
+            "book:book1",
+            "book:cat/book1:missing",
+            "book:cat/book1:README",
+            "book:cat/book1:",
+            "book:cat/book1:ch1#nope",
+            "book:cat/book1#install",
+        ];
+
+        for dest in cases.iter() {
+            assert!(resolve_reference(&ctx, dest, &from, &src_dir).is_err(), "for {:?}", dest);
+        }
+    }
+
+    #[test]
+    fn locate_broken_references() {
+        let (temp, ctx) = library();
+        let src_dir = temp.path().join("src");
+        let chapter = src_dir.join("cat/book2/intro.md");
+        let setup = src_dir.join("cat/book2/setup.md");
+        let cases = [
+            ("# Intro\n\n{{#include setup.md:1:2}}\n\n[d](book:nope)\n",
+             SourceLine::new(&chapter, 7), "book:nope"),
+            ("# Intro\n\n{{#include setup.md}}\n\n[d](book:cat/book1)\n",
+             SourceLine::new(&setup, 3), "book:nowhere"),
+        ];
+
+        for (text, location, dest) in cases.iter() {
+            let expansion = include_files(text, &chapter, 2).unwrap();
+            let error = rewrite_file_links(&ctx, &expansion.text, &chapter, 2,
+                                           &expansion.lines, &src_dir).unwrap_err();
+            let expected = format!("Unable to resolve `{}` at {}", dest, location);
+            assert_eq!(error.to_string(), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn make_html_links() {
+        let from = Path::new("src/cat/book/part1/intro.md");
+        let src_dir = Path::new("src");
+        let cases = [
+            ("setup.md", Some("setup.html")),
+            ("setup.md#install", Some("setup.html#install")),
+            ("setup.md?q=1", Some("setup.html?q=1")),
+            ("../README.md", Some("../index.html")),
+            ("deep/readme.md#top", Some("deep/index.html#top")),
+            ("../../other/README.md", Some("../../other/index.html")),
+            ("../../../../outside.md", None),
+            ("setup.html", None),
+            ("img/logo.png", None),
+            ("#install", None),
+            ("", None),
+            ("/setup.md", None),
+            ("https://example.com/setup.md", None),
+            ("mailto:someone@example.com", None),
+        ];
+
+        for (dest, expected) in cases.iter() {
+            assert_eq!(html_link(dest, from, src_dir).as_deref(), *expected, "in {:?}", dest);
+        }
+    }
+
+    #[test]
+    fn detect_schemes() {
+        let cases = [
+            ("https://example.com", true),
+            ("mailto:someone@example.com", true),
+            ("git+ssh://host/repo", true),
+            ("book:cat/book", true),
+            ("setup.md", false),
+            ("a/b:c.md", false),
+            ("1http://x", false),
+            (":x", false),
+        ];
+
+        for (dest, expected) in cases.iter() {
+            assert_eq!(has_scheme(dest), *expected, "in {:?}", dest);
+        }
+    }
+
+    #[test]
+    fn parse_reference_definitions() {
+        let cases = [
+            ("[a]: setup.md\n", Some((5, "setup.md"))),
+            ("[a]:setup.md", Some((4, "setup.md"))),
+            ("   [a]: <setup.md> \"Title\"\n", Some((9, "setup.md"))),
+            ("[a b]: setup.md 'Title'", Some((7, "setup.md"))),
+            ("    [a]: setup.md\n", None),
+            ("[]: setup.md\n", None),
+            ("[a]:\n", None),
+            ("[a] setup.md\n", None),
+            ("text [a]: setup.md\n", None),
+        ];
+
+        for (line, expected) in cases.iter() {
+            assert_eq!(reference_definition(line), *expected, "in {:?}", line);
+        }
+    }
+
+    #[test]
+    fn rewrite_link_destinations() {
+        let cases = [
+            ("[a](setup.md)", "[a](setup.html)"),
+            ("[a](setup.md \"Title\") and [b]( <other.md>)", "[a](setup.html \"Title\") and [b]( <other.html>)"),
+            ("![i](img.md)", "![i](img.md)"),
+            ("[a][ref]\n\n[ref]: setup.md\n", "[a][ref]\n\n[ref]: setup.html\n"),
+            ("`[a](setup.md)`", "`[a](setup.md)`"),
+            ("```\n[a](setup.md)\n[ref]: setup.md\n```\n", "```\n[a](setup.md)\n[ref]: setup.md\n```\n"),
+            ("    [ref]: setup.md\n", "    [ref]: setup.md\n"),
+            ("<setup.md>", "<setup.md>"),
+            ("[a](https://x.com/a.md)", "[a](https://x.com/a.md)"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            let rewritten = rewrite_links(text, |dest, _| {
+                Ok(dest.strip_suffix(".md").filter(|_| !has_scheme(dest)).map(|stem| format!("{}.html", stem)))
+            });
+            assert_eq!(&rewritten.unwrap(), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn give_link_offsets() {
+        let text = "Intro\n\n[a](one.md)\n\n[ref]: two.md\n";
+        let mut offsets = Vec::new();
+        rewrite_links(text, |dest, offset| {
+            offsets.push((dest.to_string(), offset));
+            Ok(None)
+        }).unwrap();
+
+        assert_eq!(offsets, vec![(String::from("one.md"), 7), (String::from("two.md"), 27)]);
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use crate::book::{Book, Summary};
+use crate::config::Config;
+use crate::errors::*;
+
+pub use self::cmd::CmdPreprocessor;
+pub use self::include::IncludePreprocessor;
+pub use self::links::LinkPreprocessor;
+
+mod cmd;
+mod include;
+mod links;
+
+/// Operate on a book after it's loaded and before it's rendered, e.g. to
+/// rewrite the Markdown of its chapters.
+///
+/// The built-in `include` and `links` preprocessors run first, unless the
+/// config's `build.use-default-preprocessors` is off, then those of its
+/// `build.preprocessors` list, in order.
+pub trait Preprocessor {
+    /// The preprocessor's name, as listed in the config.
+    fn name(&self) -> &str;
+
+    /// Run this preprocessor on the book, returning the updated one.
+    fn run(&self, ctx: &PreprocessorContext, book: Book) -> Result<Book>;
+}
+
+/// Extra information for a `Preprocessor` to give it more context when
+/// processing a book.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PreprocessorContext {
+    /// The location of the library directory on disk.
+    pub root: PathBuf,
+    /// The library's configuration.
+    pub config: Config,
+    /// The tree of books and categories.
+    pub summary: Summary,
+    /// The `jungle` version the preprocessor runs with.
+    pub version: String,
+}
+
+impl PreprocessorContext {
+    /// Create a new `PreprocessorContext`.
+    pub fn new<P: Into<PathBuf>>(root: P, config: Config, summary: Summary) -> Self {
+        PreprocessorContext {
+            root: root.into(),
+            config,
+            summary,
+            version: crate::VERSION.to_string(),
+        }
+    }
+}
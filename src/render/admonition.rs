@@ -0,0 +1,250 @@
+use pulldown_cmark::{Event, Tag};
+
+use crate::utils::escape_html;
+
+/// Turn the `:::type Optional title` ... `:::` fences of some Markdown into
+/// admonitions. Their content stays Markdown, including other admonitions.
+pub fn expand_fences(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut open = 0;
+    let mut code_fence: Option<String> = None;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+
+        if let Some(ref marker) = code_fence {
+            if trimmed.starts_with(marker.as_str()) {
+                code_fence = None;
+            }
+            result.push_str(line);
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let marker_char = trimmed.chars().next().expect("checked above");
+            code_fence = Some(trimmed.chars().take_while(|&c| c == marker_char).collect());
+            result.push_str(line);
+            continue;
+        }
+
+        match trimmed.strip_prefix(":::") {
+            Some("") if open > 0 => {
+                open -= 1;
+                result.push_str("\n</div>\n\n");
+            }
+            Some(rest) if rest.starts_with(|c: char| c.is_alphanumeric()) => {
+                let (kind, title) = match rest.find(char::is_whitespace) {
+                    Some(pos) => (&rest[..pos], rest[pos..].trim()),
+                    None => (rest, ""),
+                };
+
+                open += 1;
+                result.push_str(&opening_html(kind, title));
+                result.push('\n');
+            }
+            _ => result.push_str(line),
+        }
+    }
+
+    for _ in 0..open {
+        result.push_str("\n</div>\n");
+    }
+
+    result
+}
+
+/// Turn the block quotes starting with a `[!TYPE]` marker, optionally
+/// followed by a title, into admonitions, as in
+///
+/// ```markdown
+/// > [!WARNING] Mind the gap
+/// > The rest of the block quote.
+/// ```
+pub fn convert_block_quotes(events: Vec<Event>) -> Vec<Event> {
+    let mut result = Vec::with_capacity(events.len());
+    // Whether each open block quote is an admonition
+    let mut quotes = Vec::new();
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                // The first line of the block quote, which may be a marker
+                let mut line = Vec::new();
+                let paragraph = events.next_if_eq(&Event::Start(Tag::Paragraph)).is_some();
+                if paragraph {
+                    while let Some(event) = events.peek() {
+                        match event {
+                            Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph) => break,
+                            _ => line.push(events.next().expect("peeked")),
+                        }
+                    }
+                }
+
+                let (kind, title) = match parse_marker(&line) {
+                    Some(marker) => marker,
+                    None => {
+                        quotes.push(false);
+                        result.push(event);
+                        if paragraph {
+                            result.push(Event::Start(Tag::Paragraph));
+                        }
+                        result.extend(line);
+                        continue;
+                    }
+                };
+
+                quotes.push(true);
+                if title.is_empty() {
+                    result.push(Event::Html(opening_html(&kind, "").into()));
+                } else {
+                    result.push(Event::Html(format!(
+                        "<div class=\"admonition {}\">\n<p class=\"admonition-title\">",
+                        kind.to_lowercase()).into()));
+                    result.extend(title);
+                    result.push(Event::Html("</p>\n".into()));
+                }
+
+                // The rest of the marker's paragraph, if any
+                match events.next() {
+                    Some(Event::End(Tag::Paragraph)) => {}
+                    _ => result.push(Event::Start(Tag::Paragraph)),
+                }
+            }
+            Event::End(Tag::BlockQuote) => {
+                if quotes.pop().unwrap_or(false) {
+                    result.push(Event::Html("</div>\n".into()));
+                } else {
+                    result.push(event);
+                }
+            }
+            _ => result.push(event),
+        }
+    }
+
+    result
+}
+
+/// The type and the title's events of a `[!TYPE] Optional title` line.
+fn parse_marker<'a>(line: &[Event<'a>]) -> Option<(String, Vec<Event<'a>>)> {
+    // The parser may split the marker in several text events
+    let count = line.iter().take_while(|event| matches!(event, Event::Text(_))).count();
+    let lead: String = line[..count].iter()
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect();
+
+    let rest = lead.trim_start().strip_prefix("[!")?;
+    let end = rest.find(']')?;
+    let kind = &rest[..end];
+
+    if kind.is_empty() || !kind.chars().all(char::is_alphanumeric) {
+        return None;
+    }
+
+    let mut title = Vec::new();
+    let after = rest[end + 1..].trim_start();
+    if !after.is_empty() {
+        title.push(Event::Text(after.to_string().into()));
+    }
+    title.extend(line[count..].iter().cloned());
+
+    if let Some(Event::Text(text)) = title.last() {
+        let trimmed = text.trim_end().to_string();
+        *title.last_mut().expect("checked above") = Event::Text(trimmed.into());
+    }
+
+    Some((kind.to_string(), title))
+}
+
+fn opening_html(kind: &str, title: &str) -> String {
+    let class = kind.to_lowercase();
+    let title = if title.is_empty() {
+        let mut chars = class.chars();
+        chars.next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    } else {
+        escape_html(title)
+    };
+
+    format!("<div class=\"admonition {}\">\n<p class=\"admonition-title\">{}</p>\n",
+            class, title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarkdownConfig;
+    use crate::render::render_markdown;
+
+    #[test]
+    fn expand_admonition_fences() {
+        let note = "<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n";
+        let cases = [
+            (":::note\nText\n:::\n".to_string(),
+             format!("{}\nText\n\n</div>\n\n", note)),
+            (":::Note\nText\n:::".to_string(),
+             format!("{}\nText\n\n</div>\n\n", note)),
+            (":::warning Mind <the> \"gap\"\nText\n:::\n".to_string(),
+             "<div class=\"admonition warning\">\n<p class=\"admonition-title\">Mind &lt;the&gt; &quot;gap&quot;</p>\n\nText\n\n</div>\n\n".to_string()),
+            (":::tip\n:::note\nText\n:::\n:::\n".to_string(),
+             format!("<div class=\"admonition tip\">\n<p class=\"admonition-title\">Tip</p>\n\n{}\nText\n\n</div>\n\n\n</div>\n\n", note)),
+            (":::note\nText\n".to_string(),
+             format!("{}\nText\n\n</div>\n", note)),
+            ("```\n:::note\n:::\n```\n:::\n".to_string(),
+             "```\n:::note\n:::\n```\n:::\n".to_string()),
+            ("~~~~md\n```\n:::note\n~~~~\n".to_string(),
+             "~~~~md\n```\n:::note\n~~~~\n".to_string()),
+            ("::: note\n:::\n".to_string(),
+             "::: note\n:::\n".to_string()),
+            ("Text with ::: in it\n".to_string(),
+             "Text with ::: in it\n".to_string()),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&expand_fences(text), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn convert_marked_block_quotes() {
+        let note = "<div class=\"admonition note\">\n<p class=\"admonition-title\">Note</p>\n";
+        let cases = [
+            ("> [!NOTE]\n> Text\n".to_string(),
+             format!("{}<p>Text</p>\n</div>\n", note)),
+            ("> [!NOTE]\n".to_string(),
+             format!("{}</div>\n", note)),
+            ("> [!note]\n>\n> Text\n".to_string(),
+             format!("{}<p>Text</p>\n</div>\n", note)),
+            ("> [!WARNING] Mind *the* gap\n> Text\n".to_string(),
+             "<div class=\"admonition warning\">\n<p class=\"admonition-title\">Mind <em>the</em> gap</p>\n\
+              <p>Text</p>\n</div>\n".to_string()),
+            ("> [!NOTE]\n> > [!TIP]\n> > Nested\n".to_string(),
+             format!("{}<div class=\"admonition tip\">\n<p class=\"admonition-title\">Tip</p>\n\
+                      <p>Nested</p>\n</div>\n</div>\n", note)),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render_markdown(text, &MarkdownConfig::default()), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn leave_other_block_quotes_alone() {
+        let cases = [
+            ("> Text\n", "<blockquote>\n<p>Text</p>\n</blockquote>\n"),
+            ("> [link](x.html) text\n", "<blockquote>\n<p><a href=\"x.html\">link</a> text</p>\n</blockquote>\n"),
+            ("> [!] Text\n", "<blockquote>\n<p>[!] Text</p>\n</blockquote>\n"),
+            ("> [!NO TE] Text\n", "<blockquote>\n<p>[!NO TE] Text</p>\n</blockquote>\n"),
+            ("> - [!NOTE]\n", "<blockquote>\n<ul>\n<li>[!NOTE]</li>\n</ul>\n</blockquote>\n"),
+            ("> Text\n> [!NOTE]\n", "<blockquote>\n<p>Text\n[!NOTE]</p>\n</blockquote>\n"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render_markdown(text, &MarkdownConfig::default()), expected, "in {:?}", text);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::process::Command;
+
+use crate::errors::*;
+use crate::render::{RenderContext, Renderer};
+use crate::utils::{run_command, split_command};
+
+/// The environment variable giving a command renderer the library's root.
+const ROOT_VAR: &str = "JUNGLE_ROOT";
+
+/// A renderer which runs an external command, writing the serialized
+/// `RenderContext` to its stdin.
+///
+/// The command runs in the renderer's destination directory. A program given
+/// with a relative path, such as `scripts/epub.py`, is looked up from the
+/// library's root, while the arguments are passed as they are. The library's
+/// root is also in the `JUNGLE_ROOT` environment variable, e.g. with
+///
+/// ```toml
+/// [output.epub]
+/// command = "scripts/epub.py --toc"
+/// ```
+///
+/// the library's `scripts/epub.py` gets the context on stdin and writes to
+/// `build/epub/`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdRenderer {
+    name: String,
+    cmd: String,
+}
+
+impl CmdRenderer {
+    /// Create a new `CmdRenderer` which will invoke the provided `cmd` string.
+    pub fn new(name: String, cmd: String) -> CmdRenderer {
+        CmdRenderer { name, cmd }
+    }
+
+    fn compose_command(&self, root: &Path) -> Result<Command> {
+        let (program, args) = split_command(&self.cmd)
+            .chain_err(|| format!("Invalid command for the {} renderer", self.name))?;
+
+        // Scripts given with a relative path live in the library, but the
+        // command runs in the destination directory
+        let program_path = Path::new(&program);
+        let mut cmd = if program_path.components().count() > 1 && program_path.is_relative() {
+            Command::new(root.join(program_path))
+        } else {
+            Command::new(program)
+        };
+
+        cmd.args(args).env(ROOT_VAR, root);
+        Ok(cmd)
+    }
+}
+
+impl Renderer for CmdRenderer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        info!("Invoking the \"{}\" renderer", self.name);
+
+        create_dir_all(&ctx.destination)?;
+
+        let mut cmd = self.compose_command(&ctx.root)?;
+        cmd.current_dir(&ctx.destination);
+        let output = run_command(&mut cmd, serde_json::to_vec(ctx)?, &self.name, "renderer")?;
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            debug!("[{}] {}", self.name, line);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    fn compose(cmd: &str) -> Result<Command> {
+        CmdRenderer::new(String::from("epub"), cmd.to_string()).compose_command(Path::new("/library"))
+    }
+
+    #[test]
+    fn compose_commands() {
+        let cases: [(&str, &str, &[&str]); 5] = [
+            ("python3 scripts/epub.py", "python3", &["scripts/epub.py"]),
+            ("scripts/epub.py --toc src", "/library/scripts/epub.py", &["--toc", "src"]),
+            ("./epub.sh", "/library/./epub.sh", &[]),
+            ("/usr/bin/epub 'a b' \"c\"", "/usr/bin/epub", &["a b", "c"]),
+            ("sh -c \"echo $0 > arg.txt\" src", "sh", &["-c", "echo $0 > arg.txt", "src"]),
+        ];
+
+        for (line, program, args) in cases.iter() {
+            let cmd = compose(line).unwrap();
+            let found: Vec<_> = cmd.get_args().collect();
+            let expected: Vec<_> = args.iter().map(OsStr::new).collect();
+
+            assert_eq!(cmd.get_program(), OsStr::new(program), "in {:?}", line);
+            assert_eq!(found, expected, "in {:?}", line);
+            assert!(cmd.get_envs().any(|(key, value)| key == ROOT_VAR && value == Some(OsStr::new("/library"))));
+        }
+    }
+
+    #[test]
+    fn reject_invalid_commands() {
+        for line in ["", "   ", "epub 'unclosed"].iter() {
+            assert!(compose(line).is_err(), "in {:?}", line);
+        }
+    }
+}
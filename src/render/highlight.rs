@@ -0,0 +1,149 @@
+use std::sync::OnceLock;
+
+use pulldown_cmark::{Event, Tag};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::errors::*;
+
+/// Highlighted code gets classes such as `hl-keyword`, styled by the
+/// stylesheet from `theme_css()`.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Highlight the fenced code blocks whose language is known, leaving the
+/// others as plain `<pre><code>`.
+pub fn highlight_code_blocks(events: Vec<Event>) -> Vec<Event> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut code: Option<(String, Vec<Event>)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(ref info)) if code.is_none() => {
+                code = Some((info.to_string(), vec![event]));
+            }
+            Event::End(Tag::CodeBlock(_)) if code.is_some() => {
+                let (info, mut block) = code.take().expect("checked above");
+                block.push(event);
+
+                match highlight(&info, &block) {
+                    Some(html) => result.push(Event::Html(html.into())),
+                    None => result.extend(block),
+                }
+            }
+            _ => match code {
+                Some((_, ref mut block)) => block.push(event),
+                None => result.push(event),
+            },
+        }
+    }
+
+    result
+}
+
+/// Render a code block with its syntax highlighted, if its language is known.
+fn highlight(info: &str, block: &[Event]) -> Option<String> {
+    let lang = info.split(|c: char| c == ',' || c.is_whitespace()).next()?;
+    if lang.is_empty() {
+        return None;
+    }
+
+    let syntaxes = syntax_set();
+    let syntax = syntaxes.find_syntax_by_token(lang)?;
+
+    let source: String = block.iter()
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect();
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(&source) {
+        if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+            warn!("Unable to highlight a {} code block: {}", lang, e);
+            return None;
+        }
+    }
+
+    Some(format!("<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>\n",
+                 lang, generator.finalize()))
+}
+
+/// The stylesheet for highlighted code, in one of the highlighter's
+/// built-in themes.
+pub fn theme_css(name: &str) -> Result<String> {
+    let themes = ThemeSet::load_defaults();
+
+    let theme = match themes.themes.get(name) {
+        Some(theme) => theme,
+        None => {
+            let available = themes.themes.keys().cloned().collect::<Vec<_>>().join(", ");
+            bail!("Unknown highlight theme \"{}\", expected one of: {}", name, available);
+        }
+    };
+
+    css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .chain_err(|| format!("Unable to make the stylesheet of the \"{}\" theme", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarkdownConfig;
+    use crate::render::render_markdown;
+
+    fn render(text: &str) -> String {
+        render_markdown(text, &MarkdownConfig::default())
+    }
+
+    #[test]
+    fn highlight_known_languages() {
+        let cases = [
+            ("```rust\nfn main() {}\n```\n", "language-rust"),
+            ("```rust,ignore\nlet x = 1;\n```\n", "language-rust"),
+            ("~~~py extra words\nimport sys\n~~~\n", "language-py"),
+        ];
+
+        for (text, class) in cases.iter() {
+            let html = render(text);
+            assert!(html.starts_with(&format!("<pre class=\"hl-code\"><code class=\"{}\">", class)),
+                    "in {:?}: {}", text, html);
+            assert!(html.contains("<span class=\"hl-"), "in {:?}: {}", text, html);
+        }
+    }
+
+    #[test]
+    fn escape_highlighted_code() {
+        let html = render("```rust\nlet s = \"<b>&</b>\";\n```\n");
+        assert!(html.contains("&lt;b&gt;&amp;&lt;/b&gt;"), "{}", html);
+        assert!(!html.contains("<b>"), "{}", html);
+    }
+
+    #[test]
+    fn leave_other_code_blocks_alone() {
+        let cases = [
+            ("```\nplain <text>\n```\n", "<pre><code>plain &lt;text&gt;\n</code></pre>\n"),
+            ("```no-such-language\nx\n```\n", "<pre><code class=\"language-no-such-language\">x\n</code></pre>\n"),
+            ("    fn main() {}\n", "<pre><code>fn main() {}\n</code></pre>\n"),
+            ("`fn main() {}`", "<p><code>fn main() {}</code></p>\n"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render(text), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn make_theme_stylesheets() {
+        assert!(theme_css("InspiredGitHub").unwrap().contains(".hl-"));
+        assert!(theme_css("base16-ocean.dark").is_ok());
+        assert!(theme_css("no-such-theme").is_err());
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::BTreeMap;
+use std::fs::{copy, create_dir_all};
+use std::path::{Path, PathBuf};
+
+use handlebars::Handlebars;
+use serde_json::value::{Map, Value as Json};
+use walkdir::WalkDir;
+
+use crate::book::*;
+use crate::config::{BookConfig, Config, BOOK_CONFIG_FILE};
+use crate::errors::*;
+use crate::render::{highlight, render_markdown, render_markdown_page, search, RenderContext, Renderer};
+use crate::theme::{BOOK, CHAPTER, INDEX, STYLE_CSS};
+use crate::utils::{copy_files_except_ext, escape_html, path_to_root, path_to_url, relative_path, write_file};
+
+/// The default renderer, writing the library as a static website.
+#[derive(Default)]
+pub struct HtmlRenderer;
+
+impl HtmlRenderer {
+    pub fn new() -> Self {
+        HtmlRenderer
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn render(&self, ctx: &RenderContext) -> Result<()> {
+        let mut data = String::new();
+        make_summary_markdown(&ctx.summary.items, &ctx.source_dir(), &ctx.books, &mut data);
+
+        render_summary(data.as_str(), ctx)?;
+        write_file(&ctx.destination, "style.css", STYLE_CSS)?;
+
+        let html_config = ctx.config.html_config()?;
+        let highlight_css = highlight::theme_css(&html_config.highlight_theme)?;
+        write_file(&ctx.destination, "highlight.css", highlight_css.as_bytes())?;
+
+        // The library's assets, then those of a multilingual library's
+        // language, which may replace them
+        let src_dir = get_src_dir(&ctx.root, &ctx.config);
+        let mut assets_dirs = vec![src_dir.join("assets")];
+        if ctx.source_dir() != src_dir {
+            assets_dirs.push(ctx.source_dir().join("assets"));
+        }
+
+        for assets_dir in assets_dirs.iter().filter(|dir| dir.is_dir()) {
+            debug!("Copying {}", assets_dir.display());
+            let dest = ctx.destination.join("assets");
+            create_dir_all(&dest)?;
+            copy_files_except_ext(assets_dir, &dest, true, &[])?;
+        }
+
+        trace!("--------------------\n");
+
+        for book in ctx.books.iter() {
+            render_book(ctx, book)?;
+        }
+
+        if html_config.search.enable {
+            search::create_files(ctx, &html_config.search)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the books' tree as a nested Markdown list, each book with its
+/// cover, authors and description.
+fn make_summary_markdown(items: &[SummaryItem], src_dir: &Path, books: &[Book], data: &mut String) {
+    for item in items {
+        match item {
+            SummaryItem::Link(e) => {
+                if e.is_book {
+                    let relative = e.path.strip_prefix(src_dir)
+                        .expect("Books are always inside the source directory");
+                    let path = path_to_url(relative.join("index.html"));
+                    let mut entry = format!("[{}]({})", e.name, path);
+
+                    if let Some(book) = books.iter().find(|book| book.root == e.path) {
+                        if let Some(ref cover) = book.cover {
+                            entry.insert_str(0, &format!("<img class=\"cover\" src=\"{}\" alt=\"\"> ",
+                                                         path_to_url(relative.join(cover))));
+                        }
+                        if !book.authors.is_empty() {
+                            entry.push_str(&format!(" <span class=\"authors\">{}</span>",
+                                                    escape_html(&book.authors.join(", "))));
+                        }
+                        if let Some(ref description) = book.description {
+                            entry.push_str(&format!(" <span class=\"description\">{}</span>",
+                                                    escape_html(description)));
+                        }
+                    }
+
+                    data.push_str(
+                        format!("{:width$}- {entry}\n", "",
+                                width = e.level * 2, entry = entry).as_str());
+                } else {
+                    data.push_str(
+                        format!("{:width$}- {name}\n", "",
+                                width = e.level * 2, name = e.name).as_str());
+                }
+
+                make_summary_markdown(&e.nested_items, src_dir, books, data);
+            }
+            SummaryItem::Separator => data.push_str("\n---\n\n"),
+        }
+    }
+}
+
+pub fn render_summary(data: &str, ctx: &RenderContext) -> Result<()> {
+    let dest = &ctx.destination;
+    let mut handlebars = Handlebars::new();
+    handlebars.register_template_string("index",
+                                        String::from_utf8(INDEX.to_owned())?)?;
+
+    let data = make_summary_data(data, ctx)?;
+    info!("json to render: {:#?}", data);
+
+    let rendered = handlebars.render("index", &data)?;
+    info!("rendered: {}", rendered);
+
+    info!("write {:?}\n", dest.join("index.html"));
+    write_file(dest.as_path(), "index.html", rendered.as_bytes())?;
+
+    Ok(())
+}
+
+pub fn make_summary_data(md: &str, ctx: &RenderContext) -> Result<Map<String, Json>> {
+    let config = &ctx.config;
+    let mut data = make_config_data(config)?;
+
+    data.insert("summary".to_owned(), json!(render_markdown(md, &config.markdown)));
+    data.insert("languages".to_owned(),
+                json!(make_language_links(ctx, None, Path::new("index.html"))));
+
+    Ok(data)
+}
+
+pub fn render_book(ctx: &RenderContext, book: &Book) -> Result<()> {
+    let mut handlebars = Handlebars::new();
+    info!("{:#?}", book);
+
+    handlebars.register_template_string("book", String::from_utf8(BOOK.to_owned())?)?;
+    handlebars.register_template_string("chapter", String::from_utf8(CHAPTER.to_owned())?)?;
+
+    let data = make_book_data(ctx, book)?;
+    debug!("json to render: {:#?}", data);
+
+    let rendered = handlebars.render("book", &data)?;
+    info!("rendered: {}", rendered);
+
+    let stripped = book.root.strip_prefix(get_books_dir(&ctx.root, &ctx.config))
+        .expect("Chapters are always inside a book");
+
+    info!("write {:?}\n", ctx.destination.join(stripped.join("index.html")));
+    write_file(ctx.destination.as_path(),
+               stripped.join("index.html"), rendered.as_bytes())?;
+
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::Separator => continue,
+        };
+
+        let data = make_chapter_data(ctx, book, ch)?;
+        debug!("json to render: {:#?}", data);
+
+        let rendered = handlebars.render("chapter", &data)?;
+
+        let stripped = stripped.join(chapter_page(book, ch));
+
+        info!("write: {:?}\n", ctx.destination.join(&stripped));
+        write_file(ctx.destination.as_path(), stripped.as_path(), rendered.as_bytes())?;
+    }
+
+    // Images and other files the chapters link to, relatively
+    let book_dest = ctx.destination.join(stripped);
+    create_dir_all(&book_dest)?;
+
+    // The untranslated chapters link to the default language's
+    let config = &ctx.config;
+    if config.library.language != config.default_language() {
+        let original = get_language_dir(&ctx.root, config.default_language(), config).join(stripped);
+        if original.is_dir() {
+            copy_book_files(ctx, &original, &load_book_config(&original)?, &book_dest)?;
+        }
+    }
+    copy_book_files(ctx, &book.root, &book.config, &book_dest)?;
+
+    Ok(())
+}
+
+/// Copy the images and other files of the book in `root`, leaving out its
+/// Markdown files, its `book.toml` and, unless drafts are built, its drafts.
+fn copy_book_files(ctx: &RenderContext, root: &Path, config: &BookConfig, dest: &Path) -> Result<()> {
+    let walker = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            let relative = entry.path().strip_prefix(root).expect("walking the book");
+            ctx.config.build.drafts || !is_draft_path(relative, config)
+        });
+
+    for entry in walker {
+        let entry = entry.chain_err(|| format!("Unable to list the files of {}", root.display()))?;
+        let relative = entry.path().strip_prefix(root).expect("walking the book");
+
+        let is_markdown = relative.extension().is_some_and(|ext| ext == "md");
+        if !entry.file_type().is_file() || is_markdown || relative == Path::new(BOOK_CONFIG_FILE) {
+            continue;
+        }
+
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+        }
+        copy(entry.path(), &target)
+            .chain_err(|| format!("Unable to copy {}", entry.path().display()))?;
+    }
+
+    Ok(())
+}
+
+pub fn make_book_data(ctx: &RenderContext, book: &Book) -> Result<Map<String, Json>> {
+    let config = &ctx.config;
+    let mut data = make_config_data(config)?;
+    let title = match config.library.title {
+        Some(ref title) => format!("{} - {}", book.name, title),
+        None => book.name.clone(),
+    };
+    data.insert("title".to_owned(), json!(title));
+    data.insert("front_matter".to_owned(), json!(book.front_matter));
+    data.insert("draft".to_owned(), json!(book.draft));
+    data.insert("authors".to_owned(), json!(book.authors));
+    data.insert("tags".to_owned(), json!(book.tags));
+    if let Some(ref description) = book.description {
+        data.insert("description".to_owned(), json!(description));
+    }
+    if let Some(ref cover) = book.cover {
+        data.insert("cover".to_owned(), json!(path_to_url(cover)));
+    }
+    if let Some(ref language) = book.language {
+        data.insert("language".to_owned(), json!(language));
+    }
+
+    if !book.readme.is_empty() {
+        let page = render_markdown_page(book.readme.as_str(), &ctx.markdown_config(book)?);
+        data.insert("content".to_owned(), json!(page.content));
+        data.insert("has_math".to_owned(), json!(page.has_math));
+    } else {
+        data.insert("name".to_owned(), json!(book.name.clone()));
+    }
+
+    let mut chapters = vec![];
+    make_chapter_list(book, &book.chapters, 0, Path::new(""), None, &mut chapters);
+    data.insert("chapters".to_owned(), json!(chapters));
+
+    let book_dir = book.root.strip_prefix(ctx.source_dir())
+        .expect("Books are always inside the source directory");
+    let path_to_root = path_to_root(book_dir.join("README.md"));
+    let languages = make_language_links(ctx, Some((book_dir, Path::new("README.md"))),
+                                        &book_dir.join("index.html"));
+    data.insert("languages".to_owned(), json!(languages));
+    data.insert("breadcrumbs".to_owned(), json!(make_breadcrumbs(ctx, book, &path_to_root)));
+    if let Some((first, page)) = chapter_pages(book).next() {
+        data.insert("next".to_owned(), json!(page_link(&first.name, path_to_url(page))));
+    }
+    data.insert("path_to_root".to_owned(), json!(path_to_root));
+
+    Ok(data)
+}
+
+pub fn make_chapter_data(ctx: &RenderContext, book: &Book, chapter: &Chapter)
+                         -> Result<Map<String, Json>> {
+    let mut data = make_config_data(&ctx.config)?;
+
+    let markdown = ctx.markdown_config(book)?;
+    let page = render_markdown_page(chapter.content.as_str(), &markdown);
+    data.insert("content".to_owned(), json!(page.content));
+    data.insert("toc".to_owned(), json!(page.outline));
+    data.insert("has_math".to_owned(), json!(page.has_math));
+    data.insert("name".to_owned(), json!(chapter.name));
+    data.insert("front_matter".to_owned(), json!(chapter.front_matter));
+    data.insert("draft".to_owned(), json!(book.draft || chapter.draft));
+    data.insert("fallback".to_owned(), json!(chapter.fallback));
+    data.insert("default_language".to_owned(), json!(ctx.config.default_language()));
+    if let Some(ref description) = chapter.front_matter.description {
+        data.insert("description".to_owned(), json!(description));
+    }
+    data.insert("book".to_owned(), json!(book.name));
+    if let Some(ref language) = book.language {
+        data.insert("language".to_owned(), json!(language));
+    }
+    data.insert("library".to_owned(),
+                json!(ctx.config.library.title.as_ref().map_or("Index", String::as_str)));
+    data.insert("title".to_owned(), json!(format!("{} - {}", chapter.name, book.name)));
+
+    // Links are relative to the chapter's page, which may be in a section
+    let page = chapter_page(book, chapter);
+    let page_dir = page.parent().expect("A page is a file");
+    let link = |ch: &Chapter, page: &Path| page_link(&ch.name, path_to_url(relative_path(page_dir, page)));
+
+    let mut chapters = vec![];
+    make_chapter_list(book, &book.chapters, 0, page_dir, Some(chapter), &mut chapters);
+    data.insert("chapters".to_owned(), json!(chapters));
+
+    // A section without its own page lists its chapters instead
+    if chapter.content.trim().is_empty() && !chapter.sub_items.is_empty() {
+        let sub_chapters: Vec<_> = chapter.sub_items.iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(ch) => Some(link(ch, &chapter_page(book, ch))),
+                BookItem::Separator => None,
+            })
+            .collect();
+        data.insert("sub_chapters".to_owned(), json!(sub_chapters));
+    }
+
+    let relative = chapter.path.strip_prefix(get_books_dir(&ctx.root, &ctx.config))
+        .expect("Chapters are always inside a book");
+    let path_to_root = path_to_root(relative);
+    data.insert("index".to_owned(), json!(format!("{}index.html", path_to_root)));
+
+    // A section may only be a directory
+    let book_dir = book.root.strip_prefix(ctx.source_dir())
+        .expect("Books are always inside the source directory");
+    let source = chapter.path.strip_prefix(&book.root).expect("Chapters are always inside a book");
+    let source = if chapter.is_section() { source.parent().unwrap_or(source) } else { source };
+    let languages = make_language_links(ctx, Some((book_dir, source)), &book_dir.join(&page));
+    data.insert("languages".to_owned(), json!(languages));
+
+    let mut breadcrumbs = make_breadcrumbs(ctx, book, &path_to_root);
+    let mut sections = Vec::new();
+    find_sections(&book.chapters, chapter, &mut sections);
+    for section in sections {
+        breadcrumbs.push(link(section, &chapter_page(book, section)));
+    }
+    data.insert("breadcrumbs".to_owned(), json!(breadcrumbs));
+
+    // The book's page comes before its first chapter
+    let pages: Vec<_> = chapter_pages(book).collect();
+    let position = pages.iter().position(|(ch, _)| *ch == chapter)
+        .expect("The chapter is in its book");
+    let previous = match position {
+        0 => page_link(&book.name, path_to_url(relative_path(page_dir, "index.html"))),
+        _ => link(pages[position - 1].0, &pages[position - 1].1),
+    };
+    data.insert("previous".to_owned(), json!(previous));
+    if let Some((next, page)) = pages.get(position + 1) {
+        data.insert("next".to_owned(), json!(link(next, page)));
+    }
+
+    data.insert("path_to_root".to_owned(), json!(path_to_root));
+
+    Ok(data)
+}
+
+/// Where a chapter's page goes, relative to its book's directory. A
+/// section's page is the `index.html` of its directory.
+pub fn chapter_page(book: &Book, ch: &Chapter) -> PathBuf {
+    let relative = ch.path.strip_prefix(&book.root)
+        .expect("Chapters are always inside a book");
+
+    if ch.is_section() {
+        relative.with_file_name("index.html")
+    } else {
+        relative.with_extension("html")
+    }
+}
+
+/// The chapters of a book in depth-first order, with their pages.
+fn chapter_pages(book: &Book) -> impl Iterator<Item = (&Chapter, PathBuf)> {
+    book.iter().filter_map(move |item| match item {
+        BookItem::Chapter(ch) => Some((ch, chapter_page(book, ch))),
+        BookItem::Separator => None,
+    })
+}
+
+/// Flatten the chapter tree into the list shown by the templates, each
+/// chapter with its nesting `level` and a link relative to `page_dir`.
+fn make_chapter_list(book: &Book,
+                     items: &[BookItem],
+                     level: usize,
+                     page_dir: &Path,
+                     active: Option<&Chapter>,
+                     list: &mut Vec<BTreeMap<String, Json>>) {
+    for item in items {
+        let mut entry = BTreeMap::new();
+        match item {
+            BookItem::Chapter(ch) => {
+                let page = relative_path(page_dir, chapter_page(book, ch));
+                entry.insert("name".to_owned(), json!(ch.name));
+                entry.insert("path".to_owned(), json!(path_to_url(page)));
+                entry.insert("level".to_owned(), json!(level));
+                entry.insert("active".to_owned(), json!(active.is_some_and(|a| a.path == ch.path)));
+                list.push(entry);
+
+                make_chapter_list(book, &ch.sub_items, level + 1, page_dir, active, list);
+            }
+            BookItem::Separator => {
+                entry.insert("separator".to_owned(), json!(true));
+                list.push(entry);
+            }
+        }
+    }
+}
+
+/// Find the sections a chapter is nested in, the outermost first.
+fn find_sections<'a>(items: &'a [BookItem], chapter: &Chapter, sections: &mut Vec<&'a Chapter>) -> bool {
+    for item in items {
+        if let BookItem::Chapter(ch) = item {
+            if ch.path == chapter.path {
+                return true;
+            }
+
+            sections.push(ch);
+            if find_sections(&ch.sub_items, chapter, sections) {
+                return true;
+            }
+            sections.pop();
+        }
+    }
+
+    false
+}
+
+fn page_link(name: &str, path: String) -> BTreeMap<String, Json> {
+    let mut link = BTreeMap::new();
+    link.insert("name".to_owned(), json!(name));
+    link.insert("path".to_owned(), json!(path));
+    link
+}
+
+/// The trail from the library's index through the categories to a book.
+/// Categories have no page, so only the index and the book are links.
+fn make_breadcrumbs(ctx: &RenderContext, book: &Book, path_to_root: &str)
+                    -> Vec<BTreeMap<String, Json>> {
+    let library = ctx.config.library.title.as_ref().map_or("Index", String::as_str);
+    let mut breadcrumbs = vec![page_link(library, format!("{}index.html", path_to_root))];
+
+    let mut trail = Vec::new();
+    if find_trail(&ctx.summary.items, &book.root, &mut trail) {
+        for link in trail {
+            let mut crumb = BTreeMap::new();
+            crumb.insert("name".to_owned(), json!(link.name));
+            if link.is_book {
+                let relative = link.path.strip_prefix(ctx.source_dir())
+                    .expect("Books are always inside the source directory");
+                let href = format!("{}{}", path_to_root, path_to_url(relative.join("index.html")));
+                crumb.insert("path".to_owned(), json!(href));
+            }
+            breadcrumbs.push(crumb);
+        }
+    }
+
+    breadcrumbs
+}
+
+/// Find the links of the summary leading to the one at `path`, the
+/// outermost first.
+fn find_trail<'a>(items: &'a [SummaryItem], path: &Path, trail: &mut Vec<&'a Link>) -> bool {
+    for item in items {
+        if let SummaryItem::Link(link) = item {
+            trail.push(link);
+            if link.path == path || find_trail(&link.nested_items, path, trail) {
+                return true;
+            }
+            trail.pop();
+        }
+    }
+
+    false
+}
+
+/// Links to a page in every language of a multilingual library it's found
+/// in, given where the page is in the output of its language and, for the
+/// pages of a book, the book's directory and the page's source in it. A
+/// book has every chapter of the default language, translated or not.
+fn make_language_links(ctx: &RenderContext, source: Option<(&Path, &Path)>, page: &Path)
+                       -> Vec<BTreeMap<String, Json>> {
+    let config = &ctx.config;
+    let current = &config.library.language;
+    let to_root = path_to_root(config.language_dir(current).join(page));
+    let default_dir = get_language_dir(&ctx.root, config.default_language(), config);
+
+    config.library.languages.iter()
+        .filter(|language| match source {
+            Some((book_dir, source)) => {
+                let dir = get_language_dir(&ctx.root, language, config).join(book_dir);
+                dir.join("README.md").is_file()
+                    && (dir.join(source).exists() || default_dir.join(book_dir).join(source).exists())
+            }
+            None => true,
+        })
+        .map(|language| {
+            let path = config.language_dir(language).join(page);
+            let mut link = BTreeMap::new();
+            link.insert("language".to_owned(), json!(language));
+            link.insert("path".to_owned(), json!(format!("{}{}", to_root, path_to_url(path))));
+            link.insert("active".to_owned(), json!(language == current));
+            link
+        })
+        .collect()
+}
+
+/// The template data every page gets from the library's configuration.
+pub fn make_config_data(config: &Config) -> Result<Map<String, Json>> {
+    let html_config = config.html_config()?;
+    let mut data = Map::new();
+
+    data.insert("language".to_owned(), json!(config.library.language));
+    data.insert("title".to_owned(),
+                json!(config.library.title.clone().unwrap_or_default()));
+    data.insert("description".to_owned(),
+                json!(config.library.description.clone().unwrap_or_default()));
+    data.insert("default_theme".to_owned(), json!(html_config.default_theme));
+    data.insert("search_enabled".to_owned(), json!(html_config.search.enable));
+    data.insert("path_to_root".to_owned(), json!(""));
+
+    Ok(data)
+}
@@ -0,0 +1,364 @@
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+
+/// Placeholders are made of letters and digits only, so Markdown leaves them
+/// alone whatever the surrounding markup.
+const PLACEHOLDER: &str = "JUNGLEMATH";
+
+/// A formula taken out of the Markdown source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    tex: String,
+    display: bool,
+    /// The formula as written, delimiters included.
+    source: String,
+}
+
+/// Replace every `$inline$` and `$$display$$` formula outside of code with a
+/// placeholder, so the Markdown parser can't mangle its underscores and
+/// asterisks. The formulas are put back by `restore()`.
+///
+/// A `$` escaped with a backslash is left alone, as is a `$` followed by a
+/// space or closed by a `$` followed by a digit, so prices in text aren't
+/// taken for formulas.
+pub fn extract(text: &str) -> (String, Vec<Formula>) {
+    let mut result = String::with_capacity(text.len());
+    let mut formulas = Vec::new();
+    // Display math may span several lines, so formulas are searched for in
+    // the whole text rather than line by line
+    let blocks = fenced_blocks(text);
+    let mut pos = 0;
+
+    while pos < text.len() {
+        if let Some(&(_, end)) = blocks.iter().find(|&&(start, _)| start == pos) {
+            result.push_str(&text[pos..end]);
+            pos = end;
+            continue;
+        }
+
+        let rest = &text[pos..];
+        let ch = rest.chars().next().expect("pos is within the text");
+
+        if ch == '\\' && rest[1..].starts_with(['$', '`']) {
+            result.push_str(&rest[..2]);
+            pos += 2;
+        } else if ch == '`' {
+            let end = code_span_end(rest);
+            result.push_str(&rest[..end]);
+            pos += end;
+        } else if let Some((tex, len, display)) = formula_at(rest) {
+            result.push_str(&format!("{}{}X", PLACEHOLDER, formulas.len()));
+            formulas.push(Formula { tex: tex.to_string(), display, source: rest[..len].to_string() });
+            pos += len;
+        } else {
+            result.push(ch);
+            pos += ch.len_utf8();
+        }
+    }
+
+    (result, formulas)
+}
+
+/// The byte ranges of the fenced code blocks of some Markdown.
+fn fenced_blocks(text: &str) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, String)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let end = offset + line.len();
+
+        match open {
+            Some((start, ref marker)) if trimmed.starts_with(marker.as_str()) => {
+                blocks.push((start, end));
+                open = None;
+            }
+            Some(_) => {}
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                let marker_char = trimmed.chars().next().expect("checked above");
+                let marker = trimmed.chars().take_while(|&c| c == marker_char).collect();
+                open = Some((offset, marker));
+            }
+            None => {}
+        }
+
+        offset = end;
+    }
+
+    if let Some((start, _)) = open {
+        blocks.push((start, text.len()));
+    }
+
+    blocks
+}
+
+/// The length of the code span at the start of `text`, or of its opening
+/// backticks when they are never closed.
+fn code_span_end(text: &str) -> usize {
+    let ticks = text.chars().take_while(|&c| c == '`').count();
+    let marker = &text[..ticks];
+
+    let mut search = ticks;
+    while let Some(found) = text[search..].find(marker) {
+        let start = search + found;
+        let run = text[start..].chars().take_while(|&c| c == '`').count();
+        if run == ticks {
+            return start + ticks;
+        }
+        search = start + run;
+    }
+
+    ticks
+}
+
+/// The formula at the start of `text`, with the length it spans and whether
+/// it's displayed on its own.
+fn formula_at(text: &str) -> Option<(&str, usize, bool)> {
+    if let Some(body) = text.strip_prefix("$$") {
+        let end = body.find("$$")?;
+        let tex = &body[..end];
+        if tex.trim().is_empty() {
+            return None;
+        }
+        return Some((tex, end + 4, true));
+    }
+
+    let body = text.strip_prefix('$')?;
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    // Inline math stays on one line, and ends at the first unescaped `$`
+    let line = &body[..body.find('\n').unwrap_or(body.len())];
+    let mut search = 0;
+    while let Some(found) = line[search..].find('$') {
+        let end = search + found;
+        if line[..end].ends_with('\\') {
+            search = end + 1;
+            continue;
+        }
+
+        let before_space = line[..end].ends_with(char::is_whitespace);
+        let before_digit = line[end + 1..].starts_with(|c: char| c.is_ascii_digit());
+        if end == 0 || before_space || before_digit {
+            return None;
+        }
+        return Some((&line[..end], end + 2, false));
+    }
+
+    None
+}
+
+/// Turn the placeholders left by `extract()` back into formulas, marked up
+/// for the math library of the theme.
+///
+/// The placeholders which ended up in code blocks, autolinks, link
+/// destinations and raw HTML, where there's no math, get back the text they
+/// replaced. Also tells whether any formula was marked up as math.
+pub fn restore<'a>(events: Vec<Event<'a>>, formulas: &[Formula]) -> (Vec<Event<'a>>, bool) {
+    if formulas.is_empty() {
+        return (events, false);
+    }
+
+    let mut result = Vec::with_capacity(events.len());
+    let mut has_math = false;
+    // How many code blocks and autolinks are open
+    let mut verbatim = 0;
+
+    for event in events {
+        match event {
+            Event::Start(tag) => {
+                if is_verbatim(&tag) {
+                    verbatim += 1;
+                }
+                result.push(Event::Start(restore_tag(tag, formulas)));
+            }
+            Event::End(tag) => {
+                if is_verbatim(&tag) {
+                    verbatim -= 1;
+                }
+                result.push(Event::End(restore_tag(tag, formulas)));
+            }
+            Event::Html(html) => result.push(Event::Html(restore_source(html, formulas))),
+            Event::InlineHtml(html) => result.push(Event::InlineHtml(restore_source(html, formulas))),
+            Event::Text(text) if verbatim > 0 => result.push(Event::Text(restore_source(text, formulas))),
+            Event::Text(text) => {
+                for piece in split_placeholders(&text, formulas) {
+                    result.push(match piece {
+                        Piece::Text(text) => Event::Text(text.to_string().into()),
+                        Piece::Formula(formula) => {
+                            has_math = true;
+                            Event::Html(to_html(formula).into())
+                        }
+                    });
+                }
+            }
+            _ => result.push(event),
+        }
+    }
+
+    (result, has_math)
+}
+
+fn is_verbatim(tag: &Tag) -> bool {
+    match tag {
+        Tag::CodeBlock(_) => true,
+        Tag::Link(kind, _, _) => *kind == LinkType::Autolink || *kind == LinkType::Email,
+        _ => false,
+    }
+}
+
+fn restore_tag<'a>(tag: Tag<'a>, formulas: &[Formula]) -> Tag<'a> {
+    match tag {
+        Tag::Link(kind, dest, title) => Tag::Link(
+            kind, restore_source(dest, formulas), restore_source(title, formulas)),
+        Tag::Image(kind, dest, title) => Tag::Image(
+            kind, restore_source(dest, formulas), restore_source(title, formulas)),
+        _ => tag,
+    }
+}
+
+/// Put back the formulas of some text as they were written.
+fn restore_source<'a>(text: CowStr<'a>, formulas: &[Formula]) -> CowStr<'a> {
+    if !text.contains(PLACEHOLDER) {
+        return text;
+    }
+
+    split_placeholders(&text, formulas).into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text,
+            Piece::Formula(formula) => formula.source.as_str(),
+        })
+        .collect::<String>()
+        .into()
+}
+
+/// A part of some text, split around the placeholders.
+enum Piece<'t, 'f> {
+    Text(&'t str),
+    Formula(&'f Formula),
+}
+
+fn split_placeholders<'t, 'f>(text: &'t str, formulas: &'f [Formula]) -> Vec<Piece<'t, 'f>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    // Where the text before the next placeholder starts
+    let mut start = 0;
+
+    while let Some(found) = rest[start..].find(PLACEHOLDER) {
+        let at = start + found;
+        let digits = &rest[at + PLACEHOLDER.len()..];
+        let len = digits.chars().take_while(char::is_ascii_digit).count();
+        let formula = digits[..len].parse::<usize>().ok()
+            .filter(|_| digits[len..].starts_with('X'))
+            .and_then(|index| formulas.get(index));
+
+        match formula {
+            Some(formula) => {
+                if at > 0 {
+                    pieces.push(Piece::Text(&rest[..at]));
+                }
+                pieces.push(Piece::Formula(formula));
+                rest = &rest[at + PLACEHOLDER.len() + len + 1..];
+                start = 0;
+            }
+            // Not one of ours, e.g. written as is in the text
+            None => start = at + PLACEHOLDER.len(),
+        }
+    }
+
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+
+    pieces
+}
+
+fn to_html(formula: &Formula) -> String {
+    let tex = formula.tex
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+
+    if formula.display {
+        format!("<span class=\"math display\">\\[{}\\]</span>", tex)
+    } else {
+        format!("<span class=\"math inline\">\\({}\\)</span>", tex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarkdownConfig;
+    use crate::render::{render_markdown, render_markdown_page};
+
+    fn render(text: &str) -> String {
+        render_markdown(text, &MarkdownConfig { math: true, ..Default::default() })
+    }
+
+    #[test]
+    fn extract_formulas() {
+        let cases = [
+            ("$x$", vec![("x", false)]),
+            ("a $x_1$ and $$\\sum_i\ny_i$$", vec![("x_1", false), ("\\sum_i\ny_i", true)]),
+            ("from $5 to $10", vec![]),
+            ("costs $5 and $10", vec![]),
+            ("$ x$ and $x $", vec![]),
+            ("\\$x$", vec![]),
+            ("`$x$`", vec![]),
+            ("```\n$x$\n```\n", vec![]),
+            ("$a\nb$", vec![]),
+        ];
+
+        for (text, expected) in cases.iter() {
+            let (_, formulas) = extract(text);
+            let found: Vec<_> = formulas.iter().map(|f| (f.tex.as_str(), f.display)).collect();
+            assert_eq!(&found, expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn render_formulas() {
+        assert_eq!(render("$a_b$"), "<p><span class=\"math inline\">\\(a_b\\)</span></p>\n");
+        assert_eq!(render("$$x < y$$"), "<p><span class=\"math display\">\\[x &lt; y\\]</span></p>\n");
+        assert_eq!(render("JUNGLEMATH7X $x$"),
+                   "<p>JUNGLEMATH7X <span class=\"math inline\">\\(x\\)</span></p>\n");
+    }
+
+    #[test]
+    fn leave_formulas_alone_outside_of_text() {
+        let cases = [
+            ("    let price = $a_b$;\n", "<pre><code>let price = $a_b$;\n</code></pre>\n"),
+            ("[l](https://x.com/$a$b)", "<p><a href=\"https://x.com/$a$b\">l</a></p>\n"),
+            ("![i](img/$a$.png \"$t$\")", "<p><img src=\"img/$a$.png\" alt=\"i\" title=\"$t$\" /></p>\n"),
+            ("<https://x.com/$a$>", "<p><a href=\"https://x.com/$a$\">https://x.com/$a$</a></p>\n"),
+            ("<div title=\"$x$\">\n</div>\n", "<div title=\"$x$\">\n</div>\n"),
+            ("a <span title=\"$x$\">b</span>", "<p>a <span title=\"$x$\">b</span></p>\n"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render(text), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn only_count_formulas_shown_as_math() {
+        let config = MarkdownConfig { math: true, ..Default::default() };
+        let cases = [
+            ("Some $x$ here", true),
+            ("```\n$x$\n```\n", false),
+            ("    $x$\n", false),
+            ("[l](https://x.com/$a$b)", false),
+            ("<div title=\"$x$\">\n</div>\n", false),
+            ("<https://x.com/$a$>", false),
+            ("`$x$` and $y$", true),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(render_markdown_page(text, &config).has_math, *expected, "in {:?}", text);
+        }
+
+        assert!(!render_markdown_page("$x$", &MarkdownConfig::default()).has_math);
+    }
+}
@@ -1,135 +1,221 @@
-use std::collections::BTreeMap;
-use std::error::Error;
-use std::fs::{File, read_to_string};
-use std::path::{Path, PathBuf};
-
-use handlebars::{
-    Context, Handlebars, Helper, JsonRender, Output, RenderError, to_json,
-};
-use pulldown_cmark::{CowStr, Event, html, Options, Parser, Tag};
-use serde_json::to_string;
-use serde_json::value::{Map, Value as Json};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
 
 use crate::book::*;
+use crate::config::{Config, MarkdownConfig};
 use crate::errors::*;
-use crate::utils::{load_file_contents, write_file};
-use crate::theme::{INDEX, BOOK};
-
-pub fn render_summary(data: &str, dest: &PathBuf) -> Result<()> {
-    let mut handlebars = Handlebars::new();
-    handlebars.register_template_string("index",
-                                        String::from_utf8(INDEX.to_owned())?)?;
-
-    let data = make_summary_data(data);
-    info!("json to render: {:#?}", data);
-
-    let rendered = handlebars.render("index", &data)?;
-    info!("rendered: {}", rendered);
-
-    info!("write {:?}\n", dest.join("index.html"));
-    write_file(dest.as_path(), "index.html", rendered.as_bytes())?;
-
-    Ok(())
+use crate::utils::{normalize_id, unique_id};
+
+pub use self::cmd::CmdRenderer;
+pub use self::html::*;
+
+mod admonition;
+mod cmd;
+mod highlight;
+mod html;
+mod math;
+mod punctuation;
+mod search;
+
+/// An arbitrary `jungle` backend.
+///
+/// A renderer is given the whole loaded library and writes its output into
+/// `RenderContext::destination`. Several renderers may be run on the same
+/// library, each of them listed in the config with an `[output.<name>]`
+/// table.
+pub trait Renderer {
+    /// The renderer's name, matching its `[output.<name>]` table.
+    fn name(&self) -> &str;
+
+    /// Invoke the renderer, writing the library's output.
+    fn render(&self, ctx: &RenderContext) -> Result<()>;
 }
 
-pub fn make_summary_data(md: &str) -> Map<String, Json> {
-    let mut data = Map::new();
-
-    data.insert("summary".to_owned(), json!(render_markdown(md)));
-
-    data
+pub fn render_markdown(text: &str, config: &MarkdownConfig) -> String {
+    render_markdown_page(text, config).content
 }
 
-pub fn render_book(ctx: &RenderContext) -> Result<()> {
-    let mut handlebars = Handlebars::new();
-    info!("{:#?}", ctx);
-
-    handlebars.register_template_string("book", String::from_utf8(BOOK.to_owned())?)?;
+/// A page rendered from Markdown, along with what its template needs to know
+/// about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedPage {
+    /// The page's HTML.
+    pub content: String,
+    /// The page's headings, in order.
+    pub outline: Vec<Heading>,
+    /// Whether the page has formulas, which need the math library.
+    pub has_math: bool,
+}
 
-    let data = make_book_data(&ctx.book);
-    debug!("json to render: {:#?}", data);
+/// Render the Markdown of a whole page.
+pub fn render_markdown_page(text: &str, config: &MarkdownConfig) -> RenderedPage {
+    with_page_events(text, config, |events, has_math| {
+        let mut outline = Vec::new();
+        let events = add_heading_ids(events, &mut outline);
+        let events = highlight::highlight_code_blocks(events);
+
+        // Write to String buffer.
+        let mut html_output: String = String::with_capacity(text.len() * 3 / 2);
+        pulldown_cmark::html::push_html(&mut html_output, events.into_iter());
+
+        RenderedPage {
+            content: html_output,
+            outline,
+            has_math,
+        }
+    })
+}
 
-    let rendered = handlebars.render("book", &data)?;
-    info!("rendered: {}", rendered);
+/// Parse the Markdown of a page with the enabled extensions applied, up to
+/// the headings getting their ids, and hand the events to `consume` along
+/// with whether the page shows any formula.
+///
+/// Everything working out the ids of a page's headings goes through here,
+/// so that they match the rendered page.
+fn with_page_events<T, F>(text: &str, config: &MarkdownConfig, consume: F) -> T
+    where
+        F: FnOnce(Vec<Event>, bool) -> T,
+{
+    let text = admonition::expand_fences(text);
+    let (text, formulas) = if config.math {
+        math::extract(&text)
+    } else {
+        (text, Vec::new())
+    };
 
-    let stripped = ctx.book.root.strip_prefix(get_books_dir(&ctx.root))
-        .expect("Chapters are always inside a book");
+    let parser = Parser::new_ext(&text, markdown_options(config));
 
-    info!("write {:?}\n", ctx.destination.join(stripped.join("README.html")));
-    write_file(ctx.destination.as_path(),
-               stripped.join("README.html"), rendered.as_bytes())?;
+    let (mut events, has_math) = math::restore(parser.collect(), &formulas);
+    events = admonition::convert_block_quotes(events);
+    if config.smart_punctuation {
+        events = punctuation::smarten(events);
+    }
 
-    for ch in ctx.book.iter() {
-        let md = read_to_string(ch.path.as_path())?;
-        let data = render_markdown(md.as_str());
+    consume(events, has_math)
+}
 
-        let stripped = ch.path.parent().unwrap().strip_prefix(get_books_dir(&ctx.root))
-            .expect("Chapters are always inside a book");
+/// The parser's options for the enabled Markdown extensions.
+pub fn markdown_options(config: &MarkdownConfig) -> Options {
+    let mut options = Options::empty();
+    if config.tables {
+        options.insert(Options::ENABLE_TABLES);
+    }
+    if config.footnotes {
+        options.insert(Options::ENABLE_FOOTNOTES);
+    }
+    if config.strikethrough {
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+    }
+    if config.tasklists {
+        options.insert(Options::ENABLE_TASKLISTS);
+    }
+    options
+}
 
-        let filename = ch.path.file_stem().unwrap();
-        let stripped = stripped.join(
-            PathBuf::from(filename).with_extension("html"));
+/// A heading of a page, as listed in its table of contents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heading {
+    /// From 1 for `<h1>` to 6 for `<h6>`.
+    pub level: i32,
+    /// The heading's `id`, to link to it.
+    pub id: String,
+    /// The heading's plain text.
+    pub name: String,
+}
 
-        info!("write: {:?}\n", ctx.destination.join(&stripped));
-        write_file(ctx.destination.as_path(), stripped.as_path(), data.as_bytes())?;
+/// Give every heading an `id` and a permalink, recording them in `outline`.
+fn add_heading_ids<'a>(events: Vec<Event<'a>>, outline: &mut Vec<Heading>) -> Vec<Event<'a>> {
+    let mut used_ids = HashMap::new();
+    let mut result = Vec::with_capacity(events.len());
+    let mut heading: Option<(i32, Vec<Event>)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Header(level)) => heading = Some((level, Vec::new())),
+            Event::End(Tag::Header(_)) => {
+                let (level, mut inner) = heading.take().expect("headings are balanced");
+                let id = heading_id(&mut inner, &mut used_ids);
+
+                outline.push(Heading {
+                    level,
+                    id: id.clone(),
+                    name: event_text(&inner).trim().to_string(),
+                });
+
+                result.push(Event::Html(format!("<h{} id=\"{}\">", level, id).into()));
+                result.extend(inner);
+                result.push(Event::Html(format!(
+                    "<a class=\"header-anchor\" href=\"#{}\" aria-hidden=\"true\">#</a></h{}>\n",
+                    id, level).into()));
+            }
+            _ => match heading {
+                Some((_, ref mut inner)) => inner.push(event),
+                None => result.push(event),
+            },
+        }
     }
 
-    Ok(())
+    result
 }
 
-pub fn make_book_data(book: &Book) -> Map<String, Json> {
-    let mut data = Map::new();
-    let readme = book.root.join("README.md");
-    if readme.exists() {
-        let md = read_to_string(readme.as_path())
-            .unwrap_or(String::new());
-        let content = render_markdown(md.as_str());
-        data.insert("content".to_owned(), json!(content));
-    } else {
-        data.insert("name".to_owned(), json!(book.name.clone()));
-    }
+/// The `id` of a heading, unique in its page. It's either given explicitly
+/// at the end of the heading, as in `## Title {#custom-id}`, in which case
+/// it's removed from the heading's events, or made from the heading's text.
+pub fn heading_id(inner: &mut Vec<Event>, used_ids: &mut HashMap<String, usize>) -> String {
+    let id = match take_custom_id(inner) {
+        Some(id) => id,
+        None => normalize_id(&event_text(inner)),
+    };
 
-    let mut chapters = vec![];
+    unique_id(id, used_ids)
+}
 
-    for item in book.iter() {
-        let mut chapter = BTreeMap::new();
-        chapter.insert("name".to_owned(), json!(item.name));
+fn take_custom_id(inner: &mut Vec<Event>) -> Option<String> {
+    // The parser may split the text, e.g. around underscores
+    let mut trailing = String::new();
+    let mut count = 0;
+    for event in inner.iter().rev() {
+        match event {
+            Event::Text(text) => {
+                trailing.insert_str(0, text);
+                count += 1;
+            }
+            _ => break,
+        }
+    }
 
-        let href = item.path.to_str().unwrap()
-            .replace(".md", ".html")
-            .replace("src", "build");
-        chapter.insert("path".to_owned(), json!(href));
+    let text = trailing.trim_end();
+    let start = text.rfind("{#")?;
+    if !text.ends_with('}') {
+        return None;
+    }
 
-        chapters.push(chapter);
+    let id = &text[start + 2..text.len() - 1];
+    let valid = |c: char| !c.is_whitespace() && !"\"'<>&{}".contains(c);
+    if id.is_empty() || !id.chars().all(valid) {
+        return None;
     }
+    let id = id.to_string();
 
-    data.insert("chapters".to_owned(), json!(chapters));
+    let remaining = text[..start].trim_end().to_string();
+    inner.truncate(inner.len() - count);
+    if !remaining.is_empty() {
+        inner.push(Event::Text(remaining.into()));
+    }
 
-    data
+    Some(id)
 }
 
-pub fn render_markdown(text: &str) -> String {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    let parser = Parser::new_ext(text, options);
-
-    // 将md文件中的文件链接地址后缀转换为html
-    let parser = parser.map(|event| match event {
-        Event::End(Tag::Link(a, b, c)) => {
-            let html_link = b.to_owned().to_string().replace(".md", ".html");
-            Event::End(Tag::Link(a, CowStr::from(html_link), c))
-        }
-        Event::Start(Tag::Link(a, b, c)) => {
-            let html_link = b.to_owned().to_string().replace(".md", ".html");
-            Event::Start(Tag::Link(a, CowStr::from(html_link), c))
-        }
-        _ => event,
-    });
-
-    // Write to String buffer.
-    let mut html_output: String = String::with_capacity(text.len() * 3 / 2);
-    html::push_html(&mut html_output, parser);
-    html_output
+/// The plain text of some inline events, e.g. the content of a heading.
+pub fn event_text(events: &[Event]) -> String {
+    events.iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect()
 }
 
 /// The context provided to all renderers.
@@ -139,10 +225,14 @@ pub struct RenderContext {
     /// `Cargo.toml`). Useful if you know the renderer is only compatible with
     /// certain versions of `jungle`.
     pub version: String,
-    /// The book's root directory.
+    /// The library's root directory.
     pub root: PathBuf,
-    /// A loaded representation of the book itself.
-    pub book: Book,
+    /// The tree of books and categories.
+    pub summary: Summary,
+    /// A loaded representation of every book, in the summary's order.
+    pub books: Vec<Book>,
+    /// The library's configuration.
+    pub config: Config,
     /// Where the renderer *must* put any build artefacts generated. To allow
     /// renderers to cache intermediate results, this directory is not
     /// guaranteed to be empty or even exist.
@@ -153,13 +243,19 @@ pub struct RenderContext {
 
 impl RenderContext {
     /// Create a new `RenderContext`.
-    pub fn new<P, Q>(root: P, book: Book, destination: Q) -> RenderContext
+    pub fn new<P, Q>(root: P,
+                     summary: Summary,
+                     books: Vec<Book>,
+                     config: Config,
+                     destination: Q) -> RenderContext
         where
             P: Into<PathBuf>,
             Q: Into<PathBuf>,
     {
         RenderContext {
-            book,
+            summary,
+            books,
+            config,
             version: crate::VERSION.to_string(),
             root: root.into(),
             destination: destination.into(),
@@ -169,7 +265,14 @@ impl RenderContext {
 
     /// Get the source directory's (absolute) path on disk.
     pub fn source_dir(&self) -> PathBuf {
-        self.root.join("")
+        get_books_dir(&self.root, &self.config)
+    }
+
+    /// The Markdown extensions a book is rendered with: the library's, with
+    /// the overrides of the book's `book.toml`.
+    pub fn markdown_config(&self, book: &Book) -> Result<MarkdownConfig> {
+        self.config.markdown.with_overrides(&book.config.markdown)
+            .chain_err(|| format!("Invalid Markdown options for the book {}", book.name))
     }
 }
 
@@ -0,0 +1,132 @@
+use pulldown_cmark::{Event, Tag};
+
+/// Replace straight quotes with curly ones, `--` and `---` with en and em
+/// dashes, and `...` with an ellipsis, except in code and raw HTML.
+pub fn smarten(events: Vec<Event>) -> Vec<Event> {
+    let mut in_code = false;
+    // The character before the current text, to tell opening quotes from
+    // closing ones. Inline markup such as emphasis doesn't reset it.
+    let mut previous = None;
+
+    events.into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code = true;
+                event
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code = false;
+                previous = None;
+                event
+            }
+            Event::Text(text) if !in_code => {
+                let smart = smarten_text(&text, &mut previous);
+                Event::Text(smart.into())
+            }
+            Event::Code(_) => {
+                previous = Some('x');
+                event
+            }
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Header(_))
+            | Event::Start(Tag::Item) | Event::Start(Tag::TableCell)
+            | Event::SoftBreak | Event::HardBreak => {
+                previous = None;
+                event
+            }
+            _ => event,
+        })
+        .collect()
+}
+
+fn smarten_text(text: &str, previous: &mut Option<char>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        let replaced = match ch {
+            '"' => if opens(*previous) { '\u{201C}' } else { '\u{201D}' },
+            '\'' => if opens(*previous) { '\u{2018}' } else { '\u{2019}' },
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    '\u{2014}'
+                } else {
+                    '\u{2013}'
+                }
+            }
+            '.' if text_continues_with(&chars, "..") => {
+                chars.next();
+                chars.next();
+                '\u{2026}'
+            }
+            _ => ch,
+        };
+
+        result.push(replaced);
+        *previous = Some(replaced);
+    }
+
+    result
+}
+
+/// Whether a quote following `previous` opens a quotation.
+fn opens(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{\u{2013}\u{2014}\u{201C}\u{2018}".contains(c),
+    }
+}
+
+fn text_continues_with<I: Iterator<Item = char> + Clone>(chars: &I, prefix: &str) -> bool {
+    let mut chars = chars.clone();
+    prefix.chars().all(|expected| chars.next() == Some(expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MarkdownConfig;
+    use crate::render::render_markdown;
+
+    fn render(text: &str) -> String {
+        render_markdown(text, &MarkdownConfig { smart_punctuation: true, ..Default::default() })
+    }
+
+    #[test]
+    fn smarten_punctuation() {
+        let cases = [
+            ("\"Hello,\" she said.", "<p>\u{201C}Hello,\u{201D} she said.</p>\n"),
+            ("It's 'quoted'", "<p>It\u{2019}s \u{2018}quoted\u{2019}</p>\n"),
+            ("pages 1--2 -- or --- not", "<p>pages 1\u{2013}2 \u{2013} or \u{2014} not</p>\n"),
+            ("Wait...", "<p>Wait\u{2026}</p>\n"),
+            ("(\"a\") [\"b\"]", "<p>(\u{201C}a\u{201D}) [\u{201C}b\u{201D}]</p>\n"),
+            ("*\"emphasis\"* and \"*inside*\"",
+             "<p><em>\u{201C}emphasis\u{201D}</em> and \u{201C}<em>inside</em>\u{201D}</p>\n"),
+            ("`code` \"after\" `x`'s", "<p><code>code</code> \u{201C}after\u{201D} <code>x</code>\u{2019}s</p>\n"),
+            ("- \"item\"\n", "<ul>\n<li>\u{201C}item\u{201D}</li>\n</ul>\n"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render(text), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn leave_code_and_html_alone() {
+        let cases = [
+            ("`\"a\" -- b...`", "<p><code>&quot;a&quot; -- b...</code></p>\n"),
+            ("```\n\"a\" -- b...\n```\n", "<pre><code>&quot;a&quot; -- b...\n</code></pre>\n"),
+            ("<span title=\"a--b\">x</span>", "<p><span title=\"a--b\">x</span></p>\n"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            assert_eq!(&render(text), expected, "in {:?}", text);
+        }
+    }
+
+    #[test]
+    fn stay_off_by_default() {
+        assert_eq!(render_markdown("\"a\" -- b...", &MarkdownConfig::default()),
+                   "<p>&quot;a&quot; -- b...</p>\n");
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Event, Tag};
+
+use crate::book::BookItem;
+use crate::config::{MarkdownConfig, Search};
+use crate::errors::*;
+use crate::render::{chapter_page, event_text, heading_id, with_page_events, RenderContext};
+use crate::theme::SEARCH_JS;
+use crate::utils::{path_to_url, write_file};
+
+/// A section of a page, from one heading to the next.
+#[derive(Debug, Clone, Default, Serialize)]
+struct SearchDocument {
+    book: String,
+    chapter: String,
+    heading: String,
+    /// The page's location, relative to the root of the output.
+    url: String,
+    /// The heading's `id`, empty for the text before the first heading.
+    anchor: String,
+    body: String,
+}
+
+/// Write the search index of every README and chapter in the library, along
+/// with the script querying it in the browser.
+///
+/// The index is written both as JSON and as a script setting
+/// `window.searchIndex`, which still works when the pages are opened
+/// straight from the disk.
+pub fn create_files(ctx: &RenderContext, search: &Search) -> Result<()> {
+    let src_dir = ctx.source_dir();
+    let mut documents = Vec::new();
+
+    for book in ctx.books.iter() {
+        let book_dir = book.root.strip_prefix(&src_dir)
+            .expect("Books are always inside the source directory");
+        let config = ctx.markdown_config(book)?;
+        let url = path_to_url(book_dir.join("index.html"));
+        index_page(&mut documents, &book.name, &book.name, &url, &book.readme, &config);
+
+        for item in book.iter() {
+            if let BookItem::Chapter(ch) = item {
+                let url = path_to_url(book_dir.join(chapter_page(book, ch)));
+                index_page(&mut documents, &book.name, &ch.name, &url, &ch.content, &config);
+            }
+        }
+    }
+
+    let index = json!({
+        "limit_results": search.limit_results,
+        "documents": documents,
+    });
+    let index = serde_json::to_string(&index)?;
+
+    write_file(&ctx.destination, "searchindex.json", index.as_bytes())?;
+    write_file(&ctx.destination, "searchindex.js",
+               format!("window.searchIndex = {};", index).as_bytes())?;
+    write_file(&ctx.destination, "search.js", SEARCH_JS)?;
+
+    Ok(())
+}
+
+/// Split a page into one document per heading, each linking to its heading
+/// by the `id` it has on the rendered page.
+fn index_page(documents: &mut Vec<SearchDocument>, book: &str, chapter: &str, url: &str, md: &str,
+              config: &MarkdownConfig) {
+    let mut current = SearchDocument {
+        book: book.to_string(),
+        chapter: chapter.to_string(),
+        url: url.to_string(),
+        ..Default::default()
+    };
+
+    with_page_events(md, config, |events, _| {
+        let mut used_ids = HashMap::new();
+        let mut heading: Option<Vec<Event>> = None;
+
+        for event in events {
+            match event {
+                Event::Start(Tag::Header(_)) => heading = Some(Vec::new()),
+                Event::End(Tag::Header(_)) => {
+                    let mut inner = heading.take().expect("headings are balanced");
+                    let anchor = heading_id(&mut inner, &mut used_ids);
+
+                    let mut next = current.clone();
+                    next.heading = event_text(&inner).trim().to_string();
+                    next.anchor = anchor;
+                    next.body.clear();
+
+                    let previous = std::mem::replace(&mut current, next);
+                    push_document(documents, previous);
+                }
+                // A heading's events are kept whole, as the rendered page
+                // does, for the same `id`
+                _ => match heading {
+                    Some(ref mut inner) => inner.push(event),
+                    None => push_text(&mut current.body, event),
+                },
+            }
+        }
+    });
+
+    push_document(documents, current);
+}
+
+fn push_text(body: &mut String, event: Event) {
+    match event {
+        Event::Text(text) | Event::Code(text) => body.push_str(&text),
+        Event::SoftBreak | Event::HardBreak
+        | Event::End(Tag::Paragraph)
+        | Event::End(Tag::Item)
+        | Event::End(Tag::CodeBlock(_))
+        | Event::End(Tag::TableCell)
+            if !body.ends_with(' ') => {
+            body.push(' ');
+        }
+        _ => {}
+    }
+}
+
+fn push_document(documents: &mut Vec<SearchDocument>, mut doc: SearchDocument) {
+    doc.body = doc.body.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !doc.body.is_empty() || !doc.heading.is_empty() {
+        documents.push(doc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render_markdown_page;
+
+    #[test]
+    fn link_to_the_rendered_headings() {
+        let config = MarkdownConfig { math: true, smart_punctuation: true, ..Default::default() };
+        let md = "Intro\n\n## Cost of $x_1$\n\nText\n\n## a--b \"quoted\"\n\n## Setup\n\n## Setup {#setup-1}\n\n\
+                  > [!NOTE]\n> ## In a note\n\n:::tip\n## In a tip\n:::\n\n## `code` and *emphasis* {#custom}\n";
+
+        let mut documents = Vec::new();
+        index_page(&mut documents, "Book", "Chapter", "book/chapter.html", md, &config);
+        let anchors: Vec<_> = documents.iter().skip(1).map(|doc| doc.anchor.as_str()).collect();
+
+        let page = render_markdown_page(md, &config);
+        let ids: Vec<_> = page.outline.iter().map(|heading| heading.id.as_str()).collect();
+
+        assert_eq!(anchors, ids);
+        assert_eq!(documents[0].body, "Intro");
+        assert_eq!(documents[1].heading, "Cost of");
+        assert_eq!(documents[1].body, "Text");
+    }
+}
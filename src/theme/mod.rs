@@ -3,4 +3,7 @@ use std::path::Path;
 use crate::utils::load_file_contents;
 
 pub static INDEX: &[u8] = include_bytes!("index.hbs");
-pub static BOOK: &[u8] = include_bytes!("book.hbs");
\ No newline at end of file
+pub static BOOK: &[u8] = include_bytes!("book.hbs");
+pub static CHAPTER: &[u8] = include_bytes!("chapter.hbs");
+pub static STYLE_CSS: &[u8] = include_bytes!("style.css");
+pub static SEARCH_JS: &[u8] = include_bytes!("search.js");
\ No newline at end of file
@@ -0,0 +1,117 @@
+"use strict";
+(function () {
+    var index = window.searchIndex;
+    var bar = document.getElementById("searchbar");
+    var results = document.getElementById("searchresults");
+    if (!index || !bar || !results) {
+        return;
+    }
+
+    var root = window.path_to_root || "";
+    var documents = index.documents.map(function (doc) {
+        return {
+            doc: doc,
+            title: (doc.chapter + " " + doc.heading).toLowerCase(),
+            body: doc.body.toLowerCase()
+        };
+    });
+
+    function escapeHtml(text) {
+        return text.replace(/&/g, "&amp;")
+            .replace(/</g, "&lt;")
+            .replace(/>/g, "&gt;")
+            .replace(/"/g, "&quot;");
+    }
+
+    function count(haystack, needle) {
+        var n = 0;
+        var pos = haystack.indexOf(needle);
+        while (pos !== -1) {
+            n += 1;
+            pos = haystack.indexOf(needle, pos + needle.length);
+        }
+        return n;
+    }
+
+    function score(entry, terms) {
+        var total = 0;
+        for (var i = 0; i < terms.length; i++) {
+            var inTitle = count(entry.title, terms[i]);
+            var inBody = count(entry.body, terms[i]);
+            if (inTitle === 0 && inBody === 0) {
+                return 0;
+            }
+            total += inTitle * 10 + inBody;
+        }
+        return total;
+    }
+
+    // A window of the body around the first term found, with every term marked
+    function snippet(doc, terms) {
+        var body = doc.body;
+        var lower = body.toLowerCase();
+        var first = -1;
+        terms.forEach(function (term) {
+            var pos = lower.indexOf(term);
+            if (pos !== -1 && (first === -1 || pos < first)) {
+                first = pos;
+            }
+        });
+
+        var start = Math.max(0, first - 40);
+        var end = Math.min(body.length, start + 160);
+        var text = escapeHtml(body.slice(start, end));
+        terms.forEach(function (term) {
+            var escaped = escapeHtml(term).replace(/[.*+?^${}()|[\]\\]/g, "\\$&");
+            text = text.replace(new RegExp(escaped, "gi"), function (match) {
+                return "<mark>" + match + "</mark>";
+            });
+        });
+
+        return (start > 0 ? "…" : "") + text + (end < body.length ? "…" : "");
+    }
+
+    function search() {
+        var terms = bar.value.toLowerCase().split(/\s+/).filter(function (t) {
+            return t.length > 0;
+        });
+        results.innerHTML = "";
+        if (terms.length === 0) {
+            return;
+        }
+
+        var found = documents
+            .map(function (entry) {
+                return { doc: entry.doc, score: score(entry, terms) };
+            })
+            .filter(function (result) {
+                return result.score > 0;
+            })
+            .sort(function (a, b) {
+                return b.score - a.score;
+            })
+            .slice(0, index.limit_results);
+
+        found.forEach(function (result) {
+            var doc = result.doc;
+            var href = root + doc.url + (doc.anchor ? "#" + encodeURIComponent(doc.anchor) : "");
+            var path = [doc.book, doc.chapter, doc.heading].filter(function (part, i, parts) {
+                return part && parts.indexOf(part) === i;
+            });
+
+            var item = document.createElement("li");
+            item.innerHTML = '<a href="' + escapeHtml(href) + '">' + escapeHtml(path.join(" › ")) + "</a>"
+                + '<p class="search-snippet">' + snippet(doc, terms) + "</p>";
+            results.appendChild(item);
+        });
+
+        if (found.length === 0) {
+            results.innerHTML = "<li>No results</li>";
+        }
+    }
+
+    bar.addEventListener("input", search);
+    if (bar.value) {
+        search();
+    }
+})();
@@ -1,7 +1,12 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::Read;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 
 use crate::errors::*;
 
@@ -21,6 +26,126 @@ pub fn load_file_contents<P: AsRef<Path>>(filename: P, dest: &mut Vec<u8>) -> Re
     Ok(())
 }
 
+/// Takes a path to a file relative to the root directory and returns a
+/// relative path going back up to it, e.g. `a/b/c.md` gives `../../`.
+pub fn path_to_root<P: AsRef<Path>>(path: P) -> String {
+    match path.as_ref().parent() {
+        Some(parent) => parent.components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .map(|_| "../")
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// The characters escaped in the URLs made from paths, which would otherwise
+/// end them or break the Markdown links they're written in.
+const URL_PATH: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'?')
+    .add(b'<').add(b'>').add(b'(').add(b')').add(b'`');
+
+/// Turn a relative path into a relative URL, e.g. `a b/c.html` into
+/// `a%20b/c.html`.
+pub fn path_to_url<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().components()
+        .map(|c| utf8_percent_encode(&c.as_os_str().to_string_lossy(), URL_PATH).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Resolve the `.` and `..` components of a path without touching the disk,
+/// e.g. `a/./b/../c` gives `a/c`.
+pub fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The path leading from the directory `from` to `to`, both being absolute
+/// or relative to the same directory, e.g. from `a/b` to `a/c/d.html` gives
+/// `../c/d.html`.
+pub fn relative_path<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> PathBuf {
+    let from = normalize_path(from);
+    let to = normalize_path(to);
+
+    let common = from.components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in from.components().skip(common) {
+        relative.push("..");
+    }
+    for component in to.components().skip(common) {
+        relative.push(component);
+    }
+
+    relative
+}
+
+/// Make the text of a heading usable as an HTML `id`. Letters and digits of
+/// every script are kept, so Chinese headings stay readable in the URL,
+/// while punctuation is dropped and spaces become single dashes, e.g.
+/// `第一章：简介 (Intro)` gives `第一章简介-intro`.
+pub fn normalize_id(content: &str) -> String {
+    let mut id = String::with_capacity(content.len());
+
+    for ch in content.trim().chars() {
+        if ch.is_alphanumeric() || ch == '_' {
+            id.extend(ch.to_lowercase());
+        } else if (ch.is_whitespace() || ch == '-') && !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+
+    while id.ends_with('-') {
+        id.pop();
+    }
+
+    id
+}
+
+/// Make an `id` unique within a page, numbering its repeats as `id-1`,
+/// `id-2`... in the order they're seen, skipping the numbers of the ids
+/// already used.
+pub fn unique_id(id: String, used: &mut HashMap<String, usize>) -> String {
+    let id = if id.is_empty() { String::from("section") } else { id };
+
+    let mut count = used.get(&id).copied().unwrap_or(0);
+    let mut unique = if count == 0 { id.clone() } else { format!("{}-{}", id, count) };
+    while used.contains_key(&unique) {
+        count += 1;
+        unique = format!("{}-{}", id, count);
+    }
+
+    used.insert(id, count + 1);
+    used.entry(unique.clone()).or_insert(1);
+
+    unique
+}
+
+/// Escape the characters HTML would read as markup, so that the text may go
+/// in an element or a quoted attribute value.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 /// Write the given data to a file, creating it first if necessary
 pub fn write_file<P: AsRef<Path>>(build_dir: &Path, filename: P, content: &[u8]) -> Result<()> {
     let path = build_dir.join(filename);
@@ -139,6 +264,56 @@ pub fn copy_files_except_ext(
 }
 
 
+/// Split a command line such as `python3 script.py --flag` into its program
+/// and arguments, quoted the way a shell would.
+pub fn split_command(line: &str) -> Result<(String, Vec<String>)> {
+    let mut words = shlex::split(line)
+        .ok_or_else(|| format!("Unable to parse the command `{}`", line))?;
+
+    if words.is_empty() {
+        bail!("The command is empty");
+    }
+
+    let program = words.remove(0);
+    Ok((program, words))
+}
+
+/// Run an external command with `input` on its stdin and return its output,
+/// failing with a `Subprocess` error when it doesn't succeed. The command is
+/// named in the messages as the `kind` called `name`, e.g. the `renderer`
+/// called `epub`, and its stderr is logged line by line.
+pub fn run_command(cmd: &mut Command, input: Vec<u8>, name: &str, kind: &str) -> Result<Output> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .chain_err(|| format!("Unable to start the \"{}\" {} ({:?})", name, kind, cmd))?;
+
+    // Written meanwhile, so that neither side blocks on a full pipe
+    let mut stdin = child.stdin.take().expect("Child has stdin");
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output()
+        .chain_err(|| format!("Error waiting for the \"{}\" {}", name, kind))?;
+
+    if let Ok(Err(e)) = writer.join() {
+        // The command may not read its input at all
+        debug!("Unable to write to the \"{}\" {}: {}", name, kind, e);
+    }
+
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        info!("[{}] {}", name, line);
+    }
+
+    if !output.status.success() {
+        let message = format!("The \"{}\" {} failed ({})", name, kind, output.status);
+        return Err(ErrorKind::Subprocess(message, output).into());
+    }
+
+    Ok(output)
+}
+
 /// Prints a "backtrace" of some `Error`.
 pub fn log_backtrace(e: &Error) {
     error!("Error: {}", e);
@@ -147,3 +322,52 @@ pub fn log_backtrace(e: &Error) {
         error!("\tCaused By: {}", cause);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ids() {
+        let cases = [
+            ("Getting started", "getting-started"),
+            ("  Getting   Started  ", "getting-started"),
+            ("What's `new`?", "whats-new"),
+            ("snake_case and kebab-case", "snake_case-and-kebab-case"),
+            ("a - b -- c", "a-b-c"),
+            ("- Leading and trailing -", "leading-and-trailing"),
+            ("1.2.3 Release", "123-release"),
+            ("第一章：简介 (Intro)", "第一章简介-intro"),
+            ("Über Straße", "über-straße"),
+            ("ΑΒΓ", "αβγ"),
+            ("!?", ""),
+            ("", ""),
+        ];
+
+        for (content, expected) in cases.iter() {
+            assert_eq!(normalize_id(content), *expected, "in {:?}", content);
+        }
+    }
+
+    #[test]
+    fn make_unique_ids() {
+        let cases: [(&[&str], &[&str]); 4] = [
+            (&["intro", "intro", "", "intro", ""], &["intro", "intro-1", "section", "intro-2", "section-1"]),
+            (&["setup", "setup-1", "setup"], &["setup", "setup-1", "setup-2"]),
+            (&["setup-1", "setup", "setup"], &["setup-1", "setup", "setup-2"]),
+            (&["setup", "setup", "setup-1"], &["setup", "setup-1", "setup-1-1"]),
+        ];
+
+        for (ids, expected) in cases.iter() {
+            let mut used = HashMap::new();
+            let unique: Vec<_> = ids.iter().map(|id| unique_id(id.to_string(), &mut used)).collect();
+            assert_eq!(&unique, expected, "for {:?}", ids);
+        }
+    }
+
+    #[test]
+    fn escape_html_text() {
+        assert_eq!(escape_html("Tom & \"Jerry\" <3"), "Tom &amp; &quot;Jerry&quot; &lt;3");
+        assert_eq!(escape_html("O'Reilly"), "O'Reilly");
+    }
+}